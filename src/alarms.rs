@@ -0,0 +1,488 @@
+use crate::qrs_parser::BeatType;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// Beats kept for the rhythm rules
+const BEAT_HISTORY: usize = 64;
+
+// R-R intervals checked for irregularity, and the fewest needed to judge it
+const AF_INTERVALS: usize = 32;
+const AF_MIN_INTERVALS: usize = 16;
+
+// Peak-to-peak movement below which the ECG counts as flat
+const FLATLINE_MV: f32 = 0.02;
+
+// Audio pause after SILENCE, as on most bedside monitors
+const SILENCE_SECONDS: f64 = 120.0;
+
+const HISTORY_LENGTH: usize = 50;
+
+// IEC 60601-1-8 alarm priorities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AlarmPriority {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmKind {
+    Asystole,
+    VentricularTachycardia,
+    Bradycardia,
+    Tachycardia,
+    Spo2Low,
+    Spo2High,
+    AtrialFibrillation,
+    SignalLoss,
+}
+
+impl AlarmKind {
+    pub const ALL: [AlarmKind; 8] = [
+        AlarmKind::Asystole,
+        AlarmKind::VentricularTachycardia,
+        AlarmKind::Bradycardia,
+        AlarmKind::Tachycardia,
+        AlarmKind::Spo2Low,
+        AlarmKind::Spo2High,
+        AlarmKind::AtrialFibrillation,
+        AlarmKind::SignalLoss,
+    ];
+
+    pub fn priority(&self) -> AlarmPriority {
+        match self {
+            AlarmKind::Asystole | AlarmKind::VentricularTachycardia => AlarmPriority::High,
+            AlarmKind::Bradycardia
+            | AlarmKind::Tachycardia
+            | AlarmKind::Spo2Low
+            | AlarmKind::SignalLoss => AlarmPriority::Medium,
+            AlarmKind::Spo2High | AlarmKind::AtrialFibrillation => AlarmPriority::Low,
+        }
+    }
+
+    // Life-threatening alarms stay on screen after the condition ends until
+    // someone acknowledges them
+    pub fn is_latching(&self) -> bool {
+        self.priority() == AlarmPriority::High
+    }
+}
+
+impl fmt::Display for AlarmKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            AlarmKind::Asystole => "ASYSTOLE",
+            AlarmKind::VentricularTachycardia => "V-TACH",
+            AlarmKind::Bradycardia => "HR LOW",
+            AlarmKind::Tachycardia => "HR HIGH",
+            AlarmKind::Spo2Low => "SpO2 LOW",
+            AlarmKind::Spo2High => "SpO2 HIGH",
+            AlarmKind::AtrialFibrillation => "IRREGULAR HR",
+            AlarmKind::SignalLoss => "ECG SIGNAL LOSS",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+// Lower and upper alarm limit of one reading
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LimitRange {
+    pub low: f32,
+    pub high: f32,
+}
+
+impl LimitRange {
+    fn validated(self, default: LimitRange, bounds: RangeInclusive<f32>) -> Self {
+        let range = LimitRange {
+            low: clamp_or(self.low, default.low, bounds.clone()),
+            high: clamp_or(self.high, default.high, bounds),
+        };
+        if range.low > range.high {
+            default
+        } else {
+            range
+        }
+    }
+}
+
+// `value` clamped to `bounds`. Every limit is a positive quantity, so zero,
+// negative and NaN values are invalid and give `default` instead.
+fn clamp_or<T: PartialOrd + Copy + Default>(value: T, default: T, bounds: RangeInclusive<T>) -> T {
+    match value.partial_cmp(&T::default()) {
+        Some(std::cmp::Ordering::Greater) if value < *bounds.start() => *bounds.start(),
+        Some(std::cmp::Ordering::Greater) if value > *bounds.end() => *bounds.end(),
+        Some(std::cmp::Ordering::Greater) => value,
+        _ => default,
+    }
+}
+
+// Missing fields in a saved file fall back to the defaults
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlarmLimits {
+    // Beats per minute
+    pub heart_rate: LimitRange,
+    // Percent saturation
+    pub spo2: LimitRange,
+    // Seconds without a beat
+    pub asystole_seconds: f64,
+    // Consecutive ventricular beats, and their rate, that make a VT run
+    pub vt_beats: usize,
+    pub vt_rate_bpm: f32,
+    // RMSSD over mean R-R; sinus rhythm stays well below 0.1
+    pub af_irregularity: f64,
+    // Seconds of flat signal
+    pub flatline_seconds: f64,
+}
+
+impl Default for AlarmLimits {
+    fn default() -> Self {
+        Self {
+            heart_rate: LimitRange {
+                low: 50.0,
+                high: 120.0,
+            },
+            spo2: LimitRange {
+                low: 90.0,
+                high: 100.0,
+            },
+            asystole_seconds: 4.0,
+            vt_beats: 3,
+            vt_rate_bpm: 100.0,
+            af_irregularity: 0.1,
+            flatline_seconds: 4.0,
+        }
+    }
+}
+
+impl AlarmLimits {
+    // Settable range of each limit, shared with the settings dialog
+    pub const HEART_RATE_BOUNDS: RangeInclusive<f32> = 15.0..=300.0;
+    pub const SPO2_BOUNDS: RangeInclusive<f32> = 50.0..=100.0;
+    pub const ASYSTOLE_BOUNDS: RangeInclusive<f64> = 2.0..=15.0;
+    pub const VT_BEATS_BOUNDS: RangeInclusive<usize> = 2..=20;
+    pub const VT_RATE_BOUNDS: RangeInclusive<f32> = 60.0..=300.0;
+    pub const AF_IRREGULARITY_BOUNDS: RangeInclusive<f64> = 0.02..=0.5;
+    pub const FLATLINE_BOUNDS: RangeInclusive<f64> = 1.0..=30.0;
+
+    // Loads saved limits, clamped to the settable ranges. Values that are not
+    // positive numbers, and ranges whose low limit is above the high one, are
+    // replaced by their defaults.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let limits: Self = serde_json::from_str(&text)?;
+        Ok(limits.validated())
+    }
+
+    fn validated(self) -> Self {
+        let defaults = Self::default();
+        Self {
+            heart_rate: self
+                .heart_rate
+                .validated(defaults.heart_rate, Self::HEART_RATE_BOUNDS),
+            spo2: self.spo2.validated(defaults.spo2, Self::SPO2_BOUNDS),
+            asystole_seconds: clamp_or(
+                self.asystole_seconds,
+                defaults.asystole_seconds,
+                Self::ASYSTOLE_BOUNDS,
+            ),
+            vt_beats: clamp_or(self.vt_beats, defaults.vt_beats, Self::VT_BEATS_BOUNDS),
+            vt_rate_bpm: clamp_or(self.vt_rate_bpm, defaults.vt_rate_bpm, Self::VT_RATE_BOUNDS),
+            af_irregularity: clamp_or(
+                self.af_irregularity,
+                defaults.af_irregularity,
+                Self::AF_IRREGULARITY_BOUNDS,
+            ),
+            flatline_seconds: clamp_or(
+                self.flatline_seconds,
+                defaults.flatline_seconds,
+                Self::FLATLINE_BOUNDS,
+            ),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// An alarm on screen; `present` is false once a latched condition has ended
+#[derive(Debug, Clone)]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    pub onset: f64,
+    pub present: bool,
+    pub acknowledged: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlarmEvent {
+    pub kind: AlarmKind,
+    pub onset: f64,
+    pub end: Option<f64>,
+}
+
+// What the display needs, copied out of the engine each frame
+#[derive(Debug, Clone, Default)]
+pub struct AlarmStatus {
+    // Highest priority first
+    pub alarms: Vec<Alarm>,
+    // Newest first
+    pub history: Vec<AlarmEvent>,
+    pub silenced_for: Option<f64>,
+    pub limits: AlarmLimits,
+}
+
+// Evaluates the alarm rules over the beat stream and the detection lead. All times
+// are recording time in seconds.
+pub struct AlarmEngine {
+    limits: AlarmLimits,
+    flatline_threshold: f32,
+    now: f64,
+    first_sample: Option<f64>,
+    beats: VecDeque<(f64, BeatType)>,
+    heart_rate: Option<f32>,
+    spo2: Option<f32>,
+    // Signal level at the last time it moved more than the flatline threshold
+    signal_reference: f32,
+    last_signal_change: f64,
+    alarms: Vec<Alarm>,
+    history: VecDeque<AlarmEvent>,
+    silenced_until: Option<f64>,
+}
+
+impl AlarmEngine {
+    // `unit` is the physical unit of the samples, used to scale the flatline test
+    pub fn new(limits: AlarmLimits, unit: &str) -> Self {
+        Self {
+            limits,
+            flatline_threshold: FLATLINE_MV * units_per_millivolt(unit),
+            now: 0.0,
+            first_sample: None,
+            beats: VecDeque::with_capacity(BEAT_HISTORY),
+            heart_rate: None,
+            spo2: None,
+            signal_reference: 0.0,
+            last_signal_change: 0.0,
+            alarms: Vec::new(),
+            history: VecDeque::new(),
+            silenced_until: None,
+        }
+    }
+
+    pub fn add_sample(&mut self, time: f64, value: f32) {
+        // Playback looped back to the start, so the rhythm rules start over
+        if self.first_sample.is_some() && time < self.now {
+            self.first_sample = None;
+            self.beats.clear();
+            self.heart_rate = None;
+            self.silenced_until = None;
+        }
+
+        if self.first_sample.is_none() {
+            self.first_sample = Some(time);
+            self.signal_reference = value;
+            self.last_signal_change = time;
+        }
+        self.now = time;
+
+        if !value.is_finite() {
+            return;
+        }
+        if (value - self.signal_reference).abs() > self.flatline_threshold {
+            self.signal_reference = value;
+            self.last_signal_change = time;
+        }
+    }
+
+    pub fn add_beat(&mut self, time: f64, beat_type: BeatType) {
+        if self.beats.len() == BEAT_HISTORY {
+            self.beats.pop_front();
+        }
+        self.beats.push_back((time, beat_type));
+    }
+
+    pub fn set_heart_rate(&mut self, heart_rate: Option<f32>) {
+        self.heart_rate = heart_rate;
+    }
+
+    pub fn set_spo2(&mut self, spo2: Option<f32>) {
+        self.spo2 = spo2;
+    }
+
+    pub fn limits(&self) -> AlarmLimits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: AlarmLimits) {
+        self.limits = limits;
+    }
+
+    // Runs every rule and updates the alarms on screen and the history
+    pub fn evaluate(&mut self) {
+        for kind in AlarmKind::ALL {
+            let present = self.condition(kind);
+            let now = self.now;
+            let shown = self.alarms.iter().position(|alarm| alarm.kind == kind);
+
+            match (present, shown) {
+                (true, Some(idx)) if self.alarms[idx].present => {}
+                (true, _) => {
+                    // A new episode, including one recurring while still latched
+                    self.alarms.retain(|alarm| alarm.kind != kind);
+                    self.alarms.push(Alarm {
+                        kind,
+                        onset: now,
+                        present: true,
+                        acknowledged: false,
+                    });
+                    if self.history.len() == HISTORY_LENGTH {
+                        self.history.pop_back();
+                    }
+                    self.history.push_front(AlarmEvent {
+                        kind,
+                        onset: now,
+                        end: None,
+                    });
+                }
+                (false, Some(idx)) if self.alarms[idx].present => {
+                    if let Some(event) = self
+                        .history
+                        .iter_mut()
+                        .find(|event| event.kind == kind && event.end.is_none())
+                    {
+                        event.end = Some(now);
+                    }
+                    let alarm = &mut self.alarms[idx];
+                    alarm.present = false;
+                    if !kind.is_latching() || alarm.acknowledged {
+                        self.alarms.remove(idx);
+                    }
+                }
+                (false, _) => {}
+            }
+        }
+
+        if self.silenced_until.is_some_and(|until| self.now >= until) {
+            self.silenced_until = None;
+        }
+    }
+
+    // Stops the flashing of every alarm on screen and clears latched alarms
+    // whose condition has already ended
+    pub fn acknowledge(&mut self) {
+        self.alarms.retain(|alarm| alarm.present);
+        for alarm in &mut self.alarms {
+            alarm.acknowledged = true;
+        }
+    }
+
+    pub fn silence(&mut self) {
+        self.silenced_until = Some(self.now + SILENCE_SECONDS);
+    }
+
+    pub fn status(&self) -> AlarmStatus {
+        let mut alarms = self.alarms.clone();
+        alarms.sort_by(|a, b| {
+            b.kind
+                .priority()
+                .cmp(&a.kind.priority())
+                .then(b.onset.total_cmp(&a.onset))
+        });
+
+        AlarmStatus {
+            alarms,
+            history: self.history.iter().cloned().collect(),
+            silenced_for: self.silenced_until.map(|until| until - self.now),
+            limits: self.limits,
+        }
+    }
+
+    fn condition(&self, kind: AlarmKind) -> bool {
+        let Some(first_sample) = self.first_sample else {
+            return false;
+        };
+        let signal_loss = self.now - self.last_signal_change >= self.limits.flatline_seconds;
+
+        match kind {
+            AlarmKind::SignalLoss => signal_loss,
+            // Without a usable signal a missing beat says nothing about the heart
+            AlarmKind::Asystole => {
+                let last_beat = self.beats.back().map_or(first_sample, |&(time, _)| time);
+                !signal_loss && self.now - last_beat >= self.limits.asystole_seconds
+            }
+            AlarmKind::Bradycardia => self
+                .heart_rate
+                .is_some_and(|rate| rate < self.limits.heart_rate.low),
+            AlarmKind::Tachycardia => self
+                .heart_rate
+                .is_some_and(|rate| rate > self.limits.heart_rate.high),
+            AlarmKind::Spo2Low => self.spo2.is_some_and(|spo2| spo2 < self.limits.spo2.low),
+            AlarmKind::Spo2High => self.spo2.is_some_and(|spo2| spo2 > self.limits.spo2.high),
+            AlarmKind::VentricularTachycardia => self.ventricular_run(),
+            AlarmKind::AtrialFibrillation => self.irregular_rhythm(),
+        }
+    }
+
+    // The latest beats are a run of ventricular beats at or above the VT rate
+    fn ventricular_run(&self) -> bool {
+        let run: Vec<f64> = self
+            .beats
+            .iter()
+            .rev()
+            .take_while(|(_, beat_type)| *beat_type == BeatType::Ventricular)
+            .map(|&(time, _)| time)
+            .collect();
+        if run.len() < self.limits.vt_beats.max(2) {
+            return false;
+        }
+
+        let span = run[0] - run[run.len() - 1];
+        span > 0.0 && 60.0 * (run.len() - 1) as f64 / span >= self.limits.vt_rate_bpm as f64
+    }
+
+    // Normalized RMSSD of recent intervals between non-ventricular beats, so
+    // isolated PVCs don't look like atrial fibrillation. Intervals touching a
+    // ventricular beat break the sequence; only intervals that were adjacent
+    // are differenced.
+    fn irregular_rhythm(&self) -> bool {
+        let intervals: Vec<Option<f64>> = self
+            .beats
+            .iter()
+            .zip(self.beats.iter().skip(1))
+            .rev()
+            .take(AF_INTERVALS)
+            .map(|((start, a), (end, b))| {
+                (*a != BeatType::Ventricular && *b != BeatType::Ventricular).then(|| end - start)
+            })
+            .collect();
+        let normal: Vec<f64> = intervals.iter().flatten().copied().collect();
+        let differences: Vec<f64> = intervals
+            .windows(2)
+            .filter_map(|pair| Some(pair[1]? - pair[0]?))
+            .collect();
+        if normal.len() < AF_MIN_INTERVALS || differences.is_empty() {
+            return false;
+        }
+
+        let mean = normal.iter().sum::<f64>() / normal.len() as f64;
+        let rmssd = (differences
+            .iter()
+            .map(|difference| difference.powi(2))
+            .sum::<f64>()
+            / differences.len() as f64)
+            .sqrt();
+        mean > 0.0 && rmssd / mean > self.limits.af_irregularity
+    }
+}
+
+// Scale of a physical unit relative to millivolts; unknown units are taken as mV
+pub fn units_per_millivolt(unit: &str) -> f32 {
+    match unit.trim() {
+        "uV" | "µV" | "μV" => 1000.0,
+        "V" => 0.001,
+        _ => 1.0,
+    }
+}
@@ -0,0 +1,348 @@
+use crate::heart_rate::{HeartRateMeter, HrAveraging};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+// Cardiotocography features of the fetal heart rate trace, following the
+// FIGO 2015 consensus guidelines on intrapartum fetal monitoring
+
+// Beat-to-beat rates outside 50-240 BPM are missed or extra beats
+const MIN_FHR: f32 = 50.0;
+const MAX_FHR: f32 = 240.0;
+
+// Beat-to-beat rates combined into each point of the trace
+const SMOOTHING_BEATS: usize = 5;
+
+// Trace kept for the trend strip, and the part of it analysed
+const TREND_SECONDS: f64 = 3600.0;
+const ANALYSIS_SECONDS: f64 = 600.0;
+
+// The baseline is the mean of the stable segments, which need to cover at
+// least two minutes of the analysis window
+const STABLE_DEVIATION_BPM: f32 = 10.0;
+const MIN_STABLE_SECONDS: f64 = 120.0;
+const BASELINE_ROUNDING_BPM: f32 = 5.0;
+
+// Accelerations: more than 15 BPM above baseline for more than 15 s.
+// Decelerations: more than 15 BPM below baseline for more than 10 s.
+const EPISODE_BPM: f32 = 15.0;
+const ACCELERATION_SECONDS: f64 = 15.0;
+const DECELERATION_SECONDS: f64 = 10.0;
+
+// Variability is the median per-minute bandwidth of the stable trace. It is
+// reduced once every minute's bandwidth has stayed below 5 BPM for 50 minutes.
+const VARIABILITY_SEGMENT_SECONDS: f64 = 60.0;
+const REDUCED_VARIABILITY_BPM: f32 = 5.0;
+const REDUCED_VARIABILITY_SECONDS: f64 = 50.0 * 60.0;
+
+// Normal baseline range
+pub const NORMAL_BASELINE_BPM: (f32, f32) = (110.0, 160.0);
+
+#[derive(Debug, Clone, Copy)]
+pub struct FhrPoint {
+    pub time: f64,
+    pub bpm: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Episode {
+    pub start: f64,
+    pub end: f64,
+    // Largest distance from baseline in BPM
+    pub depth: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CtgAnalysis {
+    pub baseline: Option<f32>,
+    pub variability: Option<f32>,
+    // Bandwidth below 5 BPM for more than 50 minutes
+    pub reduced_variability: bool,
+    pub accelerations: Vec<Episode>,
+    pub decelerations: Vec<Episode>,
+}
+
+// Fetal heart rate trace built from beat times in seconds
+#[derive(Debug, Clone, Default)]
+pub struct FhrTrend {
+    last_beat: Option<f64>,
+    // Latest beat-to-beat rates, smoothed into the trace
+    recent_rates: VecDeque<f32>,
+    points: Vec<FhrPoint>,
+}
+
+impl FhrTrend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_beat(&mut self, time: f64) {
+        // Playback looped back to the start
+        if self.last_beat.is_some_and(|last| time <= last) {
+            self.points.clear();
+            self.recent_rates.clear();
+        }
+
+        if let Some(last) = self.last_beat.filter(|&last| time > last) {
+            let bpm = (60.0 / (time - last)) as f32;
+            if (MIN_FHR..=MAX_FHR).contains(&bpm) {
+                if self.recent_rates.len() == SMOOTHING_BEATS {
+                    self.recent_rates.pop_front();
+                }
+                self.recent_rates.push_back(bpm);
+
+                // Dropping the fastest and slowest of the last few rates rejects
+                // single missed or extra beats, and averaging the rest smooths
+                // beat timing jitter, much as a cardiotocograph's
+                // autocorrelation does
+                let mut rates: Vec<f32> = self.recent_rates.iter().copied().collect();
+                rates.sort_by(f32::total_cmp);
+                let trimmed = if rates.len() > 2 {
+                    &rates[1..rates.len() - 1]
+                } else {
+                    &rates[..]
+                };
+                self.points.push(FhrPoint {
+                    time,
+                    bpm: trimmed.iter().sum::<f32>() / trimmed.len() as f32,
+                });
+            }
+        }
+        self.last_beat = Some(time);
+
+        let expired = self
+            .points
+            .partition_point(|point| time - point.time > TREND_SECONDS);
+        self.points.drain(..expired);
+    }
+
+    pub fn points(&self) -> &[FhrPoint] {
+        &self.points
+    }
+
+    // Features of the last ten minutes of trace; reduced variability looks
+    // back over the whole trend
+    pub fn analyze(&self) -> CtgAnalysis {
+        let Some(latest) = self.points.last() else {
+            return CtgAnalysis::default();
+        };
+        let start = self
+            .points
+            .partition_point(|point| latest.time - point.time > ANALYSIS_SECONDS);
+        let window = &self.points[start..];
+
+        let Some(baseline) = baseline(window) else {
+            return CtgAnalysis::default();
+        };
+        let bandwidths = minute_bandwidths(&self.points, baseline);
+        let recent: Vec<f32> = bandwidths
+            .iter()
+            .filter(|&&(start, _)| start >= window[0].time)
+            .map(|&(_, bandwidth)| bandwidth)
+            .collect();
+        let variability = median(recent);
+
+        // Start of the latest unbroken run of low-bandwidth minutes
+        let reduced_since = bandwidths
+            .iter()
+            .rev()
+            .take_while(|&&(_, bandwidth)| bandwidth < REDUCED_VARIABILITY_BPM)
+            .last()
+            .map(|&(start, _)| start);
+
+        CtgAnalysis {
+            baseline: Some(baseline),
+            variability,
+            reduced_variability: reduced_since
+                .is_some_and(|start| latest.time - start > REDUCED_VARIABILITY_SECONDS),
+            accelerations: episodes(window, ACCELERATION_SECONDS, |point| point.bpm - baseline),
+            decelerations: episodes(window, DECELERATION_SECONDS, |point| baseline - point.bpm),
+        }
+    }
+}
+
+// Mean of the points near the median, rounded to 5 BPM as read off paper
+fn baseline(points: &[FhrPoint]) -> Option<f32> {
+    let mut rates: Vec<f32> = points.iter().map(|point| point.bpm).collect();
+    if rates.is_empty() {
+        return None;
+    }
+    rates.sort_by(f32::total_cmp);
+    let median = rates[rates.len() / 2];
+
+    let stable = stable_points(points, median);
+    if stable_duration(&stable) < MIN_STABLE_SECONDS {
+        return None;
+    }
+    let mean = stable.iter().map(|point| point.bpm).sum::<f32>() / stable.len() as f32;
+    Some((mean / BASELINE_ROUNDING_BPM).round() * BASELINE_ROUNDING_BPM)
+}
+
+fn stable_points(points: &[FhrPoint], level: f32) -> Vec<FhrPoint> {
+    points
+        .iter()
+        .copied()
+        .filter(|point| (point.bpm - level).abs() <= STABLE_DEVIATION_BPM)
+        .collect()
+}
+
+// Time covered by the points, counting each point's interval to the next one
+// only when no unstable stretch was removed in between
+fn stable_duration(points: &[FhrPoint]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| pair[1].time - pair[0].time)
+        .filter(|&gap| gap < 2.0)
+        .sum()
+}
+
+// (start time, bandwidth) of every well-covered minute of the stable trace
+fn minute_bandwidths(points: &[FhrPoint], baseline: f32) -> Vec<(f64, f32)> {
+    let stable = stable_points(points, baseline);
+    let Some(first) = stable.first().map(|point| point.time) else {
+        return Vec::new();
+    };
+
+    let mut bandwidths = Vec::new();
+    let mut segment_start = 0;
+    for idx in 1..=stable.len() {
+        let segment_ends = idx == stable.len()
+            || ((stable[idx].time - first) / VARIABILITY_SEGMENT_SECONDS).floor()
+                != ((stable[segment_start].time - first) / VARIABILITY_SEGMENT_SECONDS).floor();
+        if !segment_ends {
+            continue;
+        }
+
+        let segment = &stable[segment_start..idx];
+        // Partly covered minutes would understate the bandwidth
+        if stable_duration(segment) >= VARIABILITY_SEGMENT_SECONDS / 2.0 {
+            let (min, max) = segment
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), point| {
+                    (min.min(point.bpm), max.max(point.bpm))
+                });
+            bandwidths.push((segment[0].time, max - min));
+        }
+        segment_start = idx;
+    }
+
+    bandwidths
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    Some(values[values.len() / 2])
+}
+
+// Stretches where `deviation` stays above the episode threshold for longer
+// than `min_seconds`
+fn episodes(
+    points: &[FhrPoint],
+    min_seconds: f64,
+    deviation: impl Fn(&FhrPoint) -> f32,
+) -> Vec<Episode> {
+    let mut episodes = Vec::new();
+    let mut current: Option<Episode> = None;
+
+    for point in points {
+        let depth = deviation(point);
+        if depth > EPISODE_BPM {
+            let episode = current.get_or_insert(Episode {
+                start: point.time,
+                end: point.time,
+                depth,
+            });
+            episode.end = point.time;
+            episode.depth = episode.depth.max(depth);
+        } else if let Some(episode) = current.take() {
+            if episode.end - episode.start > min_seconds {
+                episodes.push(episode);
+            }
+        }
+    }
+    if let Some(episode) = current.filter(|episode| episode.end - episode.start > min_seconds) {
+        episodes.push(episode);
+    }
+
+    episodes
+}
+
+// What the display needs in CTG mode. The trend is shared, so copies of the
+// status made for each repaint don't copy the trace.
+#[derive(Debug, Clone, Default)]
+pub struct CtgStatus {
+    pub maternal_rate: Option<f32>,
+    pub fetal_rate: Option<f32>,
+    pub trend: Arc<[FhrPoint]>,
+    pub analysis: CtgAnalysis,
+}
+
+// Replays maternal and fetal beat times found ahead of time in step with
+// the sample stream. Times are recording time in seconds.
+pub struct CtgMonitor {
+    maternal_beats: Vec<f64>,
+    fetal_beats: Vec<f64>,
+    // Index of the next beat of each list still to be replayed
+    next_maternal: usize,
+    next_fetal: usize,
+    maternal_meter: HeartRateMeter,
+    fetal_meter: HeartRateMeter,
+    trend: FhrTrend,
+    now: f64,
+}
+
+impl CtgMonitor {
+    pub fn new(maternal_beats: Vec<f64>, fetal_beats: Vec<f64>) -> Self {
+        Self {
+            maternal_beats,
+            fetal_beats,
+            next_maternal: 0,
+            next_fetal: 0,
+            maternal_meter: HeartRateMeter::new(HrAveraging::default()),
+            fetal_meter: HeartRateMeter::new(HrAveraging::default()),
+            trend: FhrTrend::new(),
+            now: 0.0,
+        }
+    }
+
+    // Takes in every beat up to `now`
+    pub fn advance(&mut self, now: f64) {
+        // Playback looped back to the start
+        if now < self.now {
+            *self = Self::new(
+                std::mem::take(&mut self.maternal_beats),
+                std::mem::take(&mut self.fetal_beats),
+            );
+        }
+        self.now = now;
+
+        while let Some(&beat) = self
+            .maternal_beats
+            .get(self.next_maternal)
+            .filter(|&&beat| beat <= now)
+        {
+            self.maternal_meter.add_beat(beat);
+            self.next_maternal += 1;
+        }
+        while let Some(&beat) = self
+            .fetal_beats
+            .get(self.next_fetal)
+            .filter(|&&beat| beat <= now)
+        {
+            self.fetal_meter.add_beat(beat);
+            self.trend.add_beat(beat);
+            self.next_fetal += 1;
+        }
+    }
+
+    pub fn status(&self) -> CtgStatus {
+        CtgStatus {
+            maternal_rate: self.maternal_meter.rate(self.now),
+            fetal_rate: self.fetal_meter.rate(self.now),
+            trend: self.trend.points().into(),
+            analysis: self.trend.analyze(),
+        }
+    }
+}
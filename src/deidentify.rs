@@ -0,0 +1,252 @@
+use crate::edf_parser::{parse_tals, EdfReader, PatientInfo, RecordingInfo};
+use crate::edf_writer::{encode_tal, write_field};
+use chrono::{Datelike, Duration};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+// Byte ranges of the identifying fields in the fixed header
+const PATIENT_FIELD: (usize, usize) = (8, 80);
+const RECORDING_FIELD: (usize, usize) = (88, 80);
+const START_DATE_FIELD: (usize, usize) = (168, 8);
+
+// Start date EDF+ prescribes when the real one is not known
+const UNKNOWN_START_DATE: &str = "01.01.85";
+
+pub struct DeidentifyOptions {
+    // Replaces the patient code; without one the code is left unknown ("X")
+    pub pseudonym: Option<String>,
+    // Secret mixed into the per-patient date shift so it cannot be recomputed
+    // from the patient code alone
+    pub key: String,
+    pub max_shift_days: u32,
+    // Case-insensitive patterns, '*' matching any run of characters. Annotation
+    // texts matching any of them are removed.
+    pub strip_patterns: Vec<String>,
+}
+
+impl DeidentifyOptions {
+    // The key is required; an empty one would make every shift public
+    pub fn new(key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if key.is_empty() {
+            return Err("a non-empty de-identification key is required".into());
+        }
+        Ok(Self {
+            pseudonym: None,
+            key: key.to_string(),
+            max_shift_days: 365,
+            strip_patterns: Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DeidentifyReport {
+    pub shift_days: i64,
+    pub annotations_removed: usize,
+    pub records: u32,
+}
+
+// Writes a copy of `input` with the patient and recording fields scrubbed, the
+// start date shifted back by a per-patient number of days and matching
+// annotation texts removed. Ordinary signal data is copied byte for byte.
+pub fn deidentify(
+    input: &str,
+    output: &str,
+    options: &DeidentifyOptions,
+) -> Result<DeidentifyReport, Box<dyn std::error::Error>> {
+    // The data records are copied raw, so the header must describe them exactly
+    let reader = EdfReader::open_validated(input)?;
+    let header = reader.get_header().clone();
+    let bytes_per_sample = header.file_type.bytes_per_sample();
+    let annotation_signals: Vec<bool> = (0..header.signals as usize)
+        .map(|idx| reader.is_annotation_signal(idx))
+        .collect();
+    drop(reader);
+
+    let patient = header.patient_info();
+    let recording = header.recording_info();
+
+    // The same patient always gets the same shift, so their recordings stay in order
+    let identity = patient
+        .code
+        .clone()
+        .or_else(|| patient.name.clone())
+        .unwrap_or_else(|| header.patient_id.clone());
+    let shift_days = -(date_shift_days(&identity, &options.key, options.max_shift_days) as i64);
+    let shift = Duration::days(shift_days);
+
+    let start = recording.start.map(|start| start + shift);
+    // An unknown ("X") Startdate stays unknown rather than gaining a date
+    let startdate_known = header.recording_id.split_whitespace().nth(1) != Some("X");
+    let (patient_field, recording_field) = if header.is_plus() {
+        let patient = PatientInfo {
+            code: options.pseudonym.clone(),
+            sex: patient.sex,
+            birthdate: patient.birthdate.map(|date| date + shift),
+            name: None,
+            additional: Vec::new(),
+        };
+        let recording = RecordingInfo {
+            start: start.filter(|_| startdate_known),
+            admin_code: None,
+            technician: None,
+            equipment: recording.equipment,
+            additional: Vec::new(),
+        };
+        (patient.to_field(), recording.to_field())
+    } else {
+        (
+            options.pseudonym.clone().unwrap_or_else(|| "X".to_string()),
+            String::new(),
+        )
+    };
+
+    let mut input_file = BufReader::new(File::open(input)?);
+    let mut output_file = BufWriter::new(File::create(output)?);
+
+    let mut header_bytes = vec![0u8; header.header_bytes as usize];
+    input_file.read_exact(&mut header_bytes)?;
+    replace_field(&mut header_bytes, PATIENT_FIELD, &patient_field)?;
+    replace_field(&mut header_bytes, RECORDING_FIELD, &recording_field)?;
+    let start_date = match start {
+        // dd.mm.yy only covers 1985-2084
+        Some(start) if !(1985..=2084).contains(&start.year()) => {
+            return Err(format!("shifted start date {} cannot be stored", start.date()).into());
+        }
+        Some(start) => start.format("%d.%m.%y").to_string(),
+        // A date that can't be parsed can't be shifted either, so drop it
+        None => UNKNOWN_START_DATE.to_string(),
+    };
+    replace_field(&mut header_bytes, START_DATE_FIELD, &start_date)?;
+    output_file.write_all(&header_bytes)?;
+
+    let signal_bytes: Vec<usize> = header
+        .samples_per_record
+        .iter()
+        .map(|&samples| samples as usize * bytes_per_sample)
+        .collect();
+    let mut record = vec![0u8; signal_bytes.iter().sum()];
+    let mut annotations_removed = 0;
+
+    for record_idx in 0..header.data_records {
+        input_file.read_exact(&mut record)?;
+
+        let mut offset = 0;
+        for (signal_idx, &len) in signal_bytes.iter().enumerate() {
+            if annotation_signals[signal_idx] {
+                let removed =
+                    strip_annotations(&mut record[offset..offset + len], &options.strip_patterns)
+                        .map_err(|err| format!("record {}: {}", record_idx, err))?;
+                annotations_removed += removed;
+            }
+            offset += len;
+        }
+
+        output_file.write_all(&record)?;
+    }
+    output_file.flush()?;
+
+    Ok(DeidentifyReport {
+        shift_days,
+        annotations_removed,
+        records: header.data_records,
+    })
+}
+
+fn replace_field(
+    header: &mut [u8],
+    (start, width): (usize, usize),
+    text: &str,
+) -> std::io::Result<()> {
+    let mut field = Vec::with_capacity(width);
+    write_field(&mut field, text, width)?;
+    header[start..start + width].copy_from_slice(&field);
+    Ok(())
+}
+
+// Removes matching texts from one record's annotation bytes in place and
+// returns how many were removed. Untouched records keep their exact bytes.
+fn strip_annotations(
+    bytes: &mut [u8],
+    patterns: &[String],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if patterns.is_empty() {
+        return Ok(0);
+    }
+
+    let tals = parse_tals(bytes)?;
+    let mut removed = 0;
+    let mut encoded = Vec::with_capacity(bytes.len());
+
+    for (tal_idx, tal) in tals.iter().enumerate() {
+        let kept: Vec<&str> = tal
+            .annotations
+            .iter()
+            .map(String::as_str)
+            .filter(|text| {
+                let strip = !text.is_empty()
+                    && patterns.iter().any(|pattern| wildcard_match(pattern, text));
+                if strip {
+                    removed += 1;
+                }
+                !strip
+            })
+            .collect();
+
+        // The first TAL keeps the record's timekeeping even if all its texts go
+        if tal_idx == 0 {
+            let texts = if kept.is_empty() { vec![""] } else { kept };
+            encoded.extend(encode_tal(tal.onset, tal.duration, &texts));
+        } else if !kept.is_empty() {
+            encoded.extend(encode_tal(tal.onset, tal.duration, &kept));
+        }
+    }
+
+    if removed > 0 {
+        if encoded.len() > bytes.len() {
+            return Err("re-encoded annotations do not fit the record".into());
+        }
+        encoded.resize(bytes.len(), 0);
+        bytes.copy_from_slice(&encoded);
+    }
+
+    Ok(removed)
+}
+
+// Case-insensitive match where '*' stands for any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last '*' swallow one more character and retry
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// 1..=max_days, from a 64-bit FNV-1a hash of the key and patient identity
+fn date_shift_days(identity: &str, key: &str, max_days: u32) -> u32 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in key.bytes().chain([0]).chain(identity.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    (hash % max_days.max(1) as u64) as u32 + 1
+}
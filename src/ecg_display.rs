@@ -1,190 +1,208 @@
-use crate::EcgSample;
+use crate::alarms::{
+    units_per_millivolt, AlarmEvent, AlarmKind, AlarmPriority, AlarmStatus, LimitRange,
+};
+use crate::ctg::{CtgAnalysis, CtgStatus, FhrPoint, NORMAL_BASELINE_BPM};
+use crate::leads::Lead;
+use crate::qrs_parser::BeatType;
+use crate::{ChannelInfo, EcgFrame};
 use eframe::egui;
 use std::collections::VecDeque;
+use std::fmt;
+
+// Seconds either side of an ectopic R peak drawn in the beat's color
+const ECTOPIC_HIGHLIGHT_S: f32 = 0.08;
+
+// Traces shown until the user picks others
+const DEFAULT_TRACES: usize = 3;
+
+// The small grid squares are 1 mm; traces are drawn at a calibrated gain in
+// mm/mV, 10 mm/mV being the standard, and at the standard 25 mm/s paper speed
+const POINTS_PER_MM: f32 = 5.0;
+const STANDARD_GAIN_MM_PER_MV: f32 = 10.0;
+const PAPER_SPEED_MM_PER_S: f32 = 25.0;
+
+// CTG paper runs at 1 cm/min (3 cm/min in the US); points per centimetre
+// at the 96 DPI egui scales its points to
+const CTG_PAPER_CM_PER_MIN: f32 = 1.0;
+const POINTS_PER_CM: f32 = 96.0 / 2.54;
+
+// Vertical scale of the FHR strip
+const FHR_SCALE_BPM: (f32, f32) = (50.0, 210.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenLayout {
+    // Selected traces with HR, SpO2 and HRV
+    Monitor,
+    // Standard 3x4 report of the twelve leads over a lead II rhythm strip
+    TwelveLead,
+    // Maternal and fetal heart rate with the FHR trend
+    Ctg,
+}
+
+impl ScreenLayout {
+    pub const OPTIONS: [ScreenLayout; 3] = [
+        ScreenLayout::Monitor,
+        ScreenLayout::TwelveLead,
+        ScreenLayout::Ctg,
+    ];
+}
+
+impl fmt::Display for ScreenLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScreenLayout::Monitor => "Monitor",
+            ScreenLayout::TwelveLead => "12-lead",
+            ScreenLayout::Ctg => "CTG",
+        };
+        write!(f, "{}", name)
+    }
+}
 
 pub struct EcgDisplay {
-    grid_color: egui::Color32,
     background_color: egui::Color32,
     ecg_color: egui::Color32,
     spo2_color: egui::Color32,
-    nibp_color: egui::Color32,
-    resp_color: egui::Color32,
+    fhr_color: egui::Color32,
+    mhr_color: egui::Color32,
     text_color: egui::Color32,
     vital_bg_color: egui::Color32,
     panel_bg_color: egui::Color32,
     header_bg_color: egui::Color32,
-    last_qrs_times: Vec<f64>,
-    last_update_time: f64,
+    sample_rate: f32,
+    patient_label: String,
+    channels: Vec<ChannelInfo>,
+    // Which channels are drawn as traces, by channel index
+    visible_channels: Vec<bool>,
+    recording_start: Option<chrono::NaiveDateTime>,
+    show_alarm_history: bool,
+    layout: ScreenLayout,
+    // Channel of each standard lead, in `Lead::ALL` order
+    lead_channels: Vec<Option<usize>>,
+    // Filters applied to the traces, shown in the header bar
+    filter_label: String,
+    // Trace gain chosen by the user
+    gain_mm_per_mv: f32,
 }
 
 impl EcgDisplay {
-    pub fn new() -> Self {
+    pub fn new(recording: &crate::RecordingDescriptor) -> Self {
         Self {
-            grid_color: egui::Color32::from_rgb(0, 100, 0),
             background_color: egui::Color32::from_rgb(0, 0, 0),
             ecg_color: egui::Color32::from_rgb(0, 255, 0),
             spo2_color: egui::Color32::from_rgb(0, 200, 255),
-            nibp_color: egui::Color32::from_rgb(255, 255, 255),
-            resp_color: egui::Color32::from_rgb(255, 255, 0),
+            fhr_color: egui::Color32::from_rgb(255, 120, 200),
+            mhr_color: egui::Color32::from_rgb(180, 140, 255),
             text_color: egui::Color32::from_rgb(255, 255, 255),
             vital_bg_color: egui::Color32::from_rgb(20, 20, 20),
             panel_bg_color: egui::Color32::from_rgb(10, 10, 10),
             header_bg_color: egui::Color32::from_rgb(0, 100, 200),
-            last_qrs_times: Vec::new(),
-            last_update_time: 0.0,
+            sample_rate: recording.sample_rate,
+            patient_label: Self::patient_label(recording),
+            channels: recording.channels.clone(),
+            visible_channels: (0..recording.channels.len())
+                .map(|channel| channel < DEFAULT_TRACES)
+                .collect(),
+            recording_start: recording.recording_info.start,
+            show_alarm_history: false,
+            layout: ScreenLayout::Monitor,
+            lead_channels: Lead::ALL
+                .iter()
+                .map(|&lead| {
+                    recording
+                        .channels
+                        .iter()
+                        .position(|channel| Lead::from_label(&channel.label) == Some(lead))
+                })
+                .collect(),
+            filter_label: String::new(),
+            gain_mm_per_mv: STANDARD_GAIN_MM_PER_MV,
         }
     }
 
-    fn calculate_heart_rate(&mut self, samples: &VecDeque<crate::EcgSample>) -> i32 {
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs_f64();
-
-        // Find QRS complexes in recent samples
-        for sample in samples.iter().rev().take(100) {
-            if sample.is_qrs {
-                let qrs_time = current_time
-                    - (samples.len() as f64
-                        - samples
-                            .iter()
-                            .position(|s| s.timestamp == sample.timestamp)
-                            .unwrap_or(0) as f64)
-                        / 360.0;
-
-                // Only add if not already recorded
-                if !self
-                    .last_qrs_times
-                    .iter()
-                    .any(|&t| (t - qrs_time).abs() < 0.1)
-                {
-                    self.last_qrs_times.push(qrs_time);
-                }
-            }
+    // Trace gains offered to the user, as on bedside monitors
+    pub const GAIN_OPTIONS: [f32; 5] = [2.5, 5.0, 10.0, 20.0, 40.0];
+
+    // Header bar text, e.g. "PATL: JOHN SMITH  M  DOB 02-08-1951    01-01-2011 14:30"
+    fn patient_label(recording: &crate::RecordingDescriptor) -> String {
+        let patient = &recording.patient;
+        let mut label = format!(
+            "PATL: {}",
+            patient
+                .name
+                .as_deref()
+                .or(patient.code.as_deref())
+                .unwrap_or("UNKNOWN")
+                .to_uppercase()
+        );
+        if let Some(sex) = patient.sex {
+            label.push_str(match sex {
+                crate::edf_parser::Sex::Female => "  F",
+                crate::edf_parser::Sex::Male => "  M",
+            });
         }
-
-        // Keep only recent QRS times (last 10 seconds)
-        self.last_qrs_times.retain(|&t| current_time - t < 10.0);
-
-        // Calculate heart rate from intervals
-        if self.last_qrs_times.len() >= 2 {
-            self.last_qrs_times
-                .sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let intervals: Vec<f64> = self
-                .last_qrs_times
-                .windows(2)
-                .map(|w| w[1] - w[0])
-                .collect();
-
-            if !intervals.is_empty() {
-                let avg_interval = intervals.iter().sum::<f64>() / intervals.len() as f64;
-                let bpm = 60.0 / avg_interval;
-                return bpm.round() as i32;
-            }
+        if let Some(birthdate) = patient.birthdate {
+            label.push_str(&format!("  DOB {}", birthdate.format("%d-%m-%Y")));
         }
-
-        // Default heart rate with realistic variation based on breathing
-        let breathing_effect = (current_time * 0.3).sin() * 2.0; // Respiratory sinus arrhythmia
-        let random_variation = ((current_time * 7.3).sin() * (current_time * 11.7).cos()) * 1.5;
-        (76.0 + breathing_effect + random_variation)
-            .max(65.0)
-            .min(90.0) as i32
+        if let Some(start) = recording.recording_info.start {
+            label.push_str(&format!("    {}", start.format("%d-%m-%Y %H:%M")));
+        }
+        label
     }
 
-    fn calculate_spo2(&self) -> i32 {
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs_f64();
-
-        // Simulate realistic SpO2 variation with breathing correlation
-        let base_spo2 = 97.5;
-        let breathing_effect = (current_time * 0.3).sin() * 0.5; // Small breathing correlation
-        let measurement_noise = ((current_time * 13.2).sin() * (current_time * 7.8).cos()) * 0.8;
-        (base_spo2 + breathing_effect + measurement_noise)
-            .max(94.0)
-            .min(100.0)
-            .round() as i32
+    pub fn toggle_alarm_history(&mut self) {
+        self.show_alarm_history = !self.show_alarm_history;
     }
 
-    fn calculate_blood_pressure(&self) -> (i32, i32, i32) {
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs_f64();
-
-        // Simulate realistic BP variation with physiological patterns
-        let systolic_base = 118.0;
-        let diastolic_base = 78.0;
-
-        // Slow circadian-like variation
-        let slow_trend = (current_time * 0.001).sin() * 4.0;
-        // Breathing-related variation
-        let breathing_sys = (current_time * 0.3).sin() * 3.0;
-        let breathing_dia = (current_time * 0.3).sin() * 2.0;
-        // Random measurement variation
-        let sys_noise = ((current_time * 5.7).sin() * (current_time * 9.1).cos()) * 5.0;
-        let dia_noise = ((current_time * 6.3).sin() * (current_time * 8.9).cos()) * 3.0;
-
-        let systolic = (systolic_base + slow_trend + breathing_sys + sys_noise)
-            .max(100.0)
-            .min(140.0) as i32;
-        let diastolic = (diastolic_base + slow_trend * 0.5 + breathing_dia + dia_noise)
-            .max(60.0)
-            .min(90.0) as i32;
-        let mean_pressure = ((systolic + 2 * diastolic) / 3) as i32;
-
-        (systolic, diastolic, mean_pressure)
+    // Checkboxes choosing the traces on screen
+    pub fn channel_menu(&mut self, ui: &mut egui::Ui) {
+        for (channel, visible) in self.channels.iter().zip(&mut self.visible_channels) {
+            ui.checkbox(visible, format!("{} [{}]", channel.label, channel.unit));
+        }
     }
 
-    fn calculate_respiratory_rate(&self) -> i32 {
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs_f64();
-
-        // Simulate realistic respiratory rate with natural variation
-        let base_rate = 17.5;
-        let slow_variation = (current_time * 0.008).sin() * 1.5; // Slow drift
-        let activity_effect = (current_time * 0.05).cos() * 0.8; // Activity-like variation
-        (base_rate + slow_variation + activity_effect)
-            .max(12.0)
-            .min(22.0)
-            .round() as i32
+    fn visible_channels(&self) -> Vec<usize> {
+        (0..self.channels.len())
+            .filter(|&channel| self.visible_channels[channel])
+            .collect()
     }
 
-    fn calculate_temperature(&self) -> (f32, f32, f32, f32) {
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs_f64();
+    pub fn layout(&self) -> ScreenLayout {
+        self.layout
+    }
 
-        // Simulate realistic temperature readings with sensor-specific patterns
-        // Core temperature (T1) - most stable
-        let t1_base = 37.0;
-        let t1_variation = (current_time * 0.003).sin() * 0.2 + (current_time * 0.0008).cos() * 0.1;
+    pub fn set_layout(&mut self, layout: ScreenLayout) {
+        self.layout = layout;
+    }
 
-        // Peripheral temperature (T2) - slightly more variable
-        let t2_base = 36.8;
-        let t2_variation = (current_time * 0.005).sin() * 0.3 + (current_time * 0.012).cos() * 0.15;
+    pub fn set_filter_label(&mut self, label: String) {
+        self.filter_label = label;
+    }
 
-        // Skin temperature (T3) - most variable
-        let t3_base = 36.5;
-        let t3_variation = (current_time * 0.008).sin() * 0.5 + (current_time * 0.015).cos() * 0.2;
+    pub fn gain(&self) -> f32 {
+        self.gain_mm_per_mv
+    }
 
-        // Temperature difference (T4) - core-peripheral gradient
-        let t4_base = 0.2;
-        let t4_variation = (current_time * 0.01).sin() * 0.15;
+    pub fn set_gain(&mut self, gain_mm_per_mv: f32) {
+        self.gain_mm_per_mv = gain_mm_per_mv;
+    }
 
-        (
-            ((t1_base + t1_variation).max(36.5).min(37.8)) as f32,
-            ((t2_base + t2_variation).max(36.2).min(37.3)) as f32,
-            ((t3_base + t3_variation).max(35.8).min(37.2)) as f32,
-            ((t4_base + t4_variation).max(0.0).min(0.8)) as f32,
-        )
+    // Points per unit of a channel at `gain_mm_per_mv`, from the channel's unit
+    fn channel_gain(&self, channel: usize, gain_mm_per_mv: f32) -> f32 {
+        let unit = self
+            .channels
+            .get(channel)
+            .map_or("", |info| info.unit.as_str());
+        gain_mm_per_mv * POINTS_PER_MM / units_per_millivolt(unit)
     }
 
-    pub fn draw_ecg(&mut self, ui: &mut egui::Ui, samples: &VecDeque<EcgSample>) {
+    pub fn draw_ecg(
+        &mut self,
+        ui: &mut egui::Ui,
+        samples: &VecDeque<EcgFrame>,
+        vitals: &crate::Vitals,
+        alarms: &AlarmStatus,
+    ) {
+        let time = ui.input(|input| input.time);
         let available_rect = ui.available_rect_before_wrap();
         let response = ui.allocate_rect(available_rect, egui::Sense::hover());
         let painter = ui.painter();
@@ -193,7 +211,7 @@ impl EcgDisplay {
         painter.rect_filled(response.rect, egui::Rounding::ZERO, self.background_color);
 
         // Draw header bar
-        self.draw_header_bar(&painter, &response.rect);
+        self.draw_header_bar(painter, &response.rect);
 
         // Calculate layout areas
         let header_height = 35.0;
@@ -218,10 +236,193 @@ impl EcgDisplay {
         );
 
         // Draw ECG waveforms section
-        self.draw_waveform_section(&painter, &waveform_rect, samples);
+        self.draw_waveform_section(painter, &waveform_rect, samples, vitals.ctg.as_ref());
 
         // Draw vital signs panels (HR and SpO2 only)
-        self.draw_vitals_section(&painter, &vitals_rect, samples);
+        self.draw_vitals_section(painter, &vitals_rect, vitals, alarms);
+
+        // Alarms sit on top of the waveforms
+        let banner_rect =
+            egui::Rect::from_min_size(main_rect.min, egui::Vec2::new(waveform_width, 30.0));
+        self.draw_alarm_banner(painter, &banner_rect, alarms, time);
+        if self.show_alarm_history {
+            let history_rect = egui::Rect::from_min_max(
+                banner_rect.left_bottom() + egui::Vec2::new(60.0, 10.0),
+                waveform_rect.right_bottom() - egui::Vec2::new(20.0, 20.0),
+            );
+            self.draw_alarm_history(painter, &history_rect, &alarms.history);
+        }
+
+        // A failed source freezes the traces, so say why across them
+        if let Some(error) = &vitals.source_error {
+            let color = Self::alarm_color(AlarmPriority::High);
+            let error_rect = egui::Rect::from_center_size(
+                waveform_rect.center(),
+                egui::Vec2::new(waveform_width - 40.0, 60.0),
+            );
+            painter.rect_filled(error_rect, egui::Rounding::same(4.0), self.background_color);
+            painter.rect_stroke(
+                error_rect,
+                egui::Rounding::same(4.0),
+                egui::Stroke::new(2.0, color),
+            );
+            painter.text(
+                error_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                format!("SIGNAL SOURCE STOPPED\n{}", error),
+                egui::FontId::proportional(16.0),
+                color,
+            );
+        }
+    }
+
+    fn alarm_color(priority: AlarmPriority) -> egui::Color32 {
+        match priority {
+            AlarmPriority::High => egui::Color32::from_rgb(255, 0, 0),
+            AlarmPriority::Medium => egui::Color32::from_rgb(255, 200, 0),
+            AlarmPriority::Low => egui::Color32::from_rgb(0, 200, 255),
+        }
+    }
+
+    // IEC 60601-1-8 markers: "***" high, "**" medium, "*" low priority
+    fn alarm_marker(priority: AlarmPriority) -> &'static str {
+        match priority {
+            AlarmPriority::High => "***",
+            AlarmPriority::Medium => "**",
+            AlarmPriority::Low => "*",
+        }
+    }
+
+    // Highest priority alarm first. Unacknowledged alarms flash, faster for
+    // higher priority; low priority alarms never flash.
+    fn draw_alarm_banner(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        alarms: &AlarmStatus,
+        time: f64,
+    ) {
+        let Some(top) = alarms.alarms.first() else {
+            return;
+        };
+        let priority = top.kind.priority();
+        let color = Self::alarm_color(priority);
+
+        let flash_hz = match priority {
+            AlarmPriority::High => 2.0,
+            AlarmPriority::Medium => 0.6,
+            AlarmPriority::Low => 0.0,
+        };
+        let lit = top.acknowledged || flash_hz == 0.0 || (time * flash_hz).fract() < 0.5;
+
+        let text_color = if lit {
+            painter.rect_filled(*rect, egui::Rounding::ZERO, color);
+            egui::Color32::BLACK
+        } else {
+            painter.rect_stroke(*rect, egui::Rounding::ZERO, egui::Stroke::new(2.0, color));
+            color
+        };
+
+        let mut message = format!("{} {}", Self::alarm_marker(priority), top.kind);
+        if !top.present {
+            message.push_str("  (ENDED)");
+        }
+        if alarms.alarms.len() > 1 {
+            message.push_str(&format!("   +{} MORE", alarms.alarms.len() - 1));
+        }
+        painter.text(
+            rect.left_center() + egui::Vec2::new(10.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            message,
+            egui::FontId::proportional(18.0),
+            text_color,
+        );
+
+        if let Some(silenced_for) = alarms.silenced_for {
+            let seconds = silenced_for.max(0.0).ceil() as u32;
+            painter.text(
+                rect.right_center() - egui::Vec2::new(10.0, 0.0),
+                egui::Align2::RIGHT_CENTER,
+                format!("AUDIO PAUSED {}:{:02}", seconds / 60, seconds % 60),
+                egui::FontId::proportional(14.0),
+                text_color,
+            );
+        }
+    }
+
+    fn draw_alarm_history(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        history: &[AlarmEvent],
+    ) {
+        painter.rect_filled(
+            *rect,
+            egui::Rounding::same(5.0),
+            egui::Color32::from_rgba_unmultiplied(0, 0, 0, 220),
+        );
+        painter.rect_stroke(
+            *rect,
+            egui::Rounding::same(5.0),
+            egui::Stroke::new(1.0, self.text_color),
+        );
+
+        painter.text(
+            rect.min + egui::Vec2::new(10.0, 8.0),
+            egui::Align2::LEFT_TOP,
+            "ALARM HISTORY",
+            egui::FontId::proportional(16.0),
+            self.text_color,
+        );
+        if history.is_empty() {
+            painter.text(
+                rect.min + egui::Vec2::new(10.0, 34.0),
+                egui::Align2::LEFT_TOP,
+                "No alarms",
+                egui::FontId::monospace(13.0),
+                self.text_color,
+            );
+        }
+
+        let line_height = 18.0;
+        let rows = ((rect.height() - 40.0) / line_height).max(0.0) as usize;
+        for (i, event) in history.iter().take(rows).enumerate() {
+            let duration = event.end.map_or("ongoing".to_string(), |end| {
+                format!("{:.0} s", (end - event.onset).max(0.0))
+            });
+            let priority = event.kind.priority();
+            painter.text(
+                rect.min + egui::Vec2::new(10.0, 34.0 + i as f32 * line_height),
+                egui::Align2::LEFT_TOP,
+                format!(
+                    "{}  {:<3} {:<16} {}",
+                    self.alarm_time(event.onset),
+                    Self::alarm_marker(priority),
+                    event.kind.to_string(),
+                    duration
+                ),
+                egui::FontId::monospace(13.0),
+                Self::alarm_color(priority),
+            );
+        }
+    }
+
+    // Clock time when the recording start is known, else time into the recording
+    fn alarm_time(&self, seconds: f64) -> String {
+        match self.recording_start {
+            Some(start) => (start + chrono::Duration::milliseconds((seconds * 1000.0) as i64))
+                .format("%H:%M:%S")
+                .to_string(),
+            None => {
+                let seconds = seconds.max(0.0) as u64;
+                format!(
+                    "+{:02}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )
+            }
+        }
     }
 
     fn draw_header_bar(&self, painter: &egui::Painter, rect: &egui::Rect) {
@@ -233,11 +434,20 @@ impl EcgDisplay {
         painter.text(
             header_rect.min + egui::Vec2::new(10.0, 8.0),
             egui::Align2::LEFT_TOP,
-            "BED NO: 01    PATL: DEMO PATIENT    01-01-2024",
+            format!("BED NO: 01    {}", self.patient_label),
             egui::FontId::proportional(14.0),
             egui::Color32::WHITE,
         );
 
+        // Active filters, left of the badge
+        painter.text(
+            header_rect.max - egui::Vec2::new(70.0, 17.5),
+            egui::Align2::RIGHT_CENTER,
+            &self.filter_label,
+            egui::FontId::monospace(12.0),
+            egui::Color32::WHITE,
+        );
+
         // ECG label in top right
         painter.rect_filled(
             egui::Rect::from_min_size(
@@ -250,7 +460,10 @@ impl EcgDisplay {
         painter.text(
             header_rect.max - egui::Vec2::new(35.0, 15.0),
             egui::Align2::CENTER_CENTER,
-            "ECG",
+            match self.layout {
+                ScreenLayout::Ctg => "CTG",
+                ScreenLayout::Monitor | ScreenLayout::TwelveLead => "ECG",
+            },
             egui::FontId::proportional(12.0),
             egui::Color32::BLACK,
         );
@@ -260,10 +473,44 @@ impl EcgDisplay {
         &mut self,
         painter: &egui::Painter,
         rect: &egui::Rect,
-        samples: &VecDeque<EcgSample>,
+        samples: &VecDeque<EcgFrame>,
+        ctg: Option<&CtgStatus>,
     ) {
         // Background for waveform area
         painter.rect_filled(*rect, egui::Rounding::ZERO, self.panel_bg_color);
+        let frames: Vec<&EcgFrame> = samples.iter().collect();
+
+        if self.layout == ScreenLayout::TwelveLead {
+            self.draw_medical_grid(painter, rect);
+            self.draw_twelve_lead(painter, rect, &frames);
+            return;
+        }
+
+        if self.layout == ScreenLayout::Ctg {
+            // One ECG lead on top, the FHR trend strip below
+            let ecg_height = rect.height() * 0.35;
+            let ecg_rect =
+                egui::Rect::from_min_size(rect.min, egui::Vec2::new(rect.width(), ecg_height));
+            self.draw_medical_grid(painter, &ecg_rect);
+            let lead_rect = egui::Rect::from_min_size(
+                ecg_rect.min + egui::Vec2::new(60.0, 20.0),
+                egui::Vec2::new(ecg_rect.width() - 80.0, ecg_height - 30.0),
+            );
+            let channel = self.visible_channels().first().copied().unwrap_or(0);
+            if let Some(info) = self.channels.get(channel) {
+                self.draw_ecg_label(painter, &lead_rect, info);
+                self.draw_calibration_pulse(painter, &lead_rect, self.gain_mm_per_mv);
+                let gain = self.channel_gain(channel, self.gain_mm_per_mv);
+                self.draw_realistic_ecg(painter, &lead_rect, &frames, gain, |s| s.value(channel));
+            }
+
+            let strip_rect = egui::Rect::from_min_max(
+                rect.min + egui::Vec2::new(60.0, ecg_height + 20.0),
+                rect.max - egui::Vec2::new(20.0, 20.0),
+            );
+            self.draw_fhr_strip(painter, &strip_rect, ctg);
+            return;
+        }
 
         // Draw grid
         self.draw_medical_grid(painter, rect);
@@ -277,26 +524,19 @@ impl EcgDisplay {
         let ecg_rect =
             egui::Rect::from_min_size(rect.min, egui::Vec2::new(rect.width(), ecg_height));
 
-        // Each ECG lead gets half of ECG section (only 2 leads now)
-        let lead_height = ecg_height / 2.0;
-        let ecg_leads = [
-            egui::Rect::from_min_size(
-                ecg_rect.min + egui::Vec2::new(60.0, 20.0),
-                egui::Vec2::new(ecg_rect.width() - 80.0, lead_height - 30.0),
-            ),
-            egui::Rect::from_min_size(
-                ecg_rect.min + egui::Vec2::new(60.0, lead_height + 20.0),
-                egui::Vec2::new(ecg_rect.width() - 80.0, lead_height - 30.0),
-            ),
-        ];
-
-        // Draw ECG lead labels
-        self.draw_ecg_label(painter, &ecg_leads[0], "II");
-        self.draw_ecg_label(painter, &ecg_leads[1], "V1");
-
-        // Draw ECG waveforms
-        self.draw_realistic_ecg(painter, &ecg_leads[0], samples, |s| s.lead2);
-        self.draw_realistic_ecg(painter, &ecg_leads[1], samples, |s| s.lead_v1);
+        // The selected channels share the ECG section equally
+        let channels = self.visible_channels();
+        let lead_height = ecg_height / channels.len().max(1) as f32;
+        for (row, &channel) in channels.iter().enumerate() {
+            let lead_rect = egui::Rect::from_min_size(
+                ecg_rect.min + egui::Vec2::new(60.0, row as f32 * lead_height + 10.0),
+                egui::Vec2::new(ecg_rect.width() - 80.0, lead_height - 15.0),
+            );
+            self.draw_ecg_label(painter, &lead_rect, &self.channels[channel]);
+            self.draw_calibration_pulse(painter, &lead_rect, self.gain_mm_per_mv);
+            let gain = self.channel_gain(channel, self.gain_mm_per_mv);
+            self.draw_realistic_ecg(painter, &lead_rect, &frames, gain, |s| s.value(channel));
+        }
 
         // PLETH section only (bottom 30%)
         let pleth_height = rect.height() * 0.3;
@@ -310,77 +550,280 @@ impl EcgDisplay {
         self.draw_pleth_waveform(painter, &pleth_rect, samples);
     }
 
+    // Four columns, I-III, aVR-aVF, V1-V3 and V4-V6, each a quarter of the
+    // sweep of lead II below them. Like a printed report, every lead is at
+    // 25 mm/s and the standard 10 mm/mV so amplitudes compare across leads and
+    // with other ECGs; a 250 mm wide report shows 2.5 s per column.
+    fn draw_twelve_lead(&self, painter: &egui::Painter, rect: &egui::Rect, frames: &[&EcgFrame]) {
+        let margin = 10.0;
+        // Room on the left for the calibration pulses
+        let pulse_width = 14.0;
+        let area = egui::Rect::from_min_max(
+            rect.min + egui::Vec2::new(margin + pulse_width, margin),
+            rect.max - egui::Vec2::new(margin, margin),
+        );
+        let row_height = area.height() / 4.0;
+        let column_width = area.width() / 4.0;
+        let gain = |channel| self.channel_gain(channel, STANDARD_GAIN_MM_PER_MV);
+
+        // The sweep is as long as the report is wide at paper speed
+        let sweep = (area.width() / Self::points_per_second() * self.sample_rate) as usize;
+        let frames = &frames[frames.len() - sweep.min(frames.len())..];
+
+        let label = |position: egui::Pos2, lead: Lead, recorded: bool| {
+            painter.text(
+                position + egui::Vec2::new(4.0, 2.0),
+                egui::Align2::LEFT_TOP,
+                if recorded {
+                    lead.to_string()
+                } else {
+                    format!("{} --", lead)
+                },
+                egui::FontId::proportional(13.0),
+                if recorded {
+                    self.ecg_color
+                } else {
+                    egui::Color32::GRAY
+                },
+            );
+        };
+
+        for (index, &lead) in Lead::ALL.iter().enumerate() {
+            let (column, row) = (index / 3, index % 3);
+            let cell = egui::Rect::from_min_size(
+                area.min + egui::Vec2::new(column as f32 * column_width, row as f32 * row_height),
+                egui::Vec2::new(column_width, row_height),
+            );
+            let channel = self.lead_channels[index];
+            label(cell.min, lead, channel.is_some());
+            if column == 0 {
+                self.draw_calibration_pulse(painter, &cell, STANDARD_GAIN_MM_PER_MV);
+            }
+
+            // Lead change marker at the start of every column but the first
+            if column > 0 {
+                painter.line_segment(
+                    [
+                        cell.left_center() - egui::Vec2::new(0.0, row_height * 0.2),
+                        cell.left_center() + egui::Vec2::new(0.0, row_height * 0.2),
+                    ],
+                    egui::Stroke::new(1.5, self.text_color),
+                );
+            }
+
+            let Some(channel) = channel else {
+                continue;
+            };
+            let segment = &frames[frames.len() * column / 4..frames.len() * (column + 1) / 4];
+            self.draw_realistic_ecg(
+                painter,
+                &cell.shrink2(egui::Vec2::new(3.0, 0.0)),
+                segment,
+                gain(channel),
+                |s| s.value(channel),
+            );
+        }
+
+        let rhythm = egui::Rect::from_min_size(
+            area.min + egui::Vec2::new(0.0, 3.0 * row_height),
+            egui::Vec2::new(area.width(), row_height),
+        );
+        let rhythm_channel = self.lead_channels[Lead::II as usize];
+        label(rhythm.min, Lead::II, rhythm_channel.is_some());
+        self.draw_calibration_pulse(painter, &rhythm, STANDARD_GAIN_MM_PER_MV);
+        if let Some(channel) = rhythm_channel {
+            self.draw_realistic_ecg(painter, &rhythm, frames, gain(channel), |s| {
+                s.value(channel)
+            });
+        }
+    }
+
     fn draw_vitals_section(
         &mut self,
         painter: &egui::Painter,
         rect: &egui::Rect,
-        samples: &VecDeque<EcgSample>,
+        vitals: &crate::Vitals,
+        alarms: &AlarmStatus,
     ) {
         // Background
         painter.rect_filled(*rect, egui::Rounding::ZERO, self.vital_bg_color);
 
-        let panel_height = rect.height() / 2.0; // Only 2 panels now
+        let panel_height = rect.height() / 3.0; // HR, SpO2 and HRV
         let margin = 10.0;
+        let panel_rect = |index: f32| {
+            egui::Rect::from_min_size(
+                rect.min + egui::Vec2::new(margin, panel_height * index + margin),
+                egui::Vec2::new(rect.width() - 2.0 * margin, panel_height - margin * 2.0),
+            )
+        };
+
+        if self.layout == ScreenLayout::Ctg {
+            let ctg = vitals.ctg.as_ref();
+            let normal_baseline = LimitRange {
+                low: NORMAL_BASELINE_BPM.0,
+                high: NORMAL_BASELINE_BPM.1,
+            };
+            self.draw_rate_panel(
+                painter,
+                &panel_rect(0.0),
+                "FHR",
+                ctg.and_then(|ctg| ctg.fetal_rate),
+                &normal_baseline,
+                self.fhr_color,
+            );
+            self.draw_rate_panel(
+                painter,
+                &panel_rect(1.0),
+                "MHR",
+                ctg.and_then(|ctg| ctg.maternal_rate),
+                &alarms.limits.heart_rate,
+                self.mhr_color,
+            );
+            self.draw_ctg_panel(painter, &panel_rect(2.0), ctg.map(|ctg| &ctg.analysis));
+            return;
+        }
 
-        // Heart Rate Panel (ECG) - Top half
-        let hr_rect = egui::Rect::from_min_size(
-            rect.min + egui::Vec2::new(margin, margin),
-            egui::Vec2::new(rect.width() - 2.0 * margin, panel_height - margin * 2.0),
+        // Heart Rate Panel (ECG) - top
+        self.draw_hr_panel(
+            painter,
+            &panel_rect(0.0),
+            vitals.heart_rate,
+            &alarms.limits.heart_rate,
+            &vitals.ectopy,
         );
-        self.draw_hr_panel(painter, &hr_rect, samples);
 
-        // SpO2 Panel - Bottom half
-        let spo2_rect = egui::Rect::from_min_size(
-            rect.min + egui::Vec2::new(margin, panel_height + margin),
-            egui::Vec2::new(rect.width() - 2.0 * margin, panel_height - margin * 2.0),
-        );
-        self.draw_spo2_panel(painter, &spo2_rect);
+        // SpO2 Panel - middle
+        self.draw_spo2_panel(painter, &panel_rect(1.0), vitals.spo2, &alarms.limits.spo2);
+
+        // HRV Panel - bottom
+        self.draw_hrv_panel(painter, &panel_rect(2.0), vitals.hrv.as_ref());
     }
 
-    fn draw_hr_panel(
-        &mut self,
+    fn draw_hrv_panel(
+        &self,
         painter: &egui::Painter,
         rect: &egui::Rect,
-        samples: &VecDeque<EcgSample>,
+        hrv: Option<&crate::hrv::HrvMetrics>,
     ) {
-        // Dark background
         painter.rect_filled(
             *rect,
             egui::Rounding::same(5.0),
             egui::Color32::from_rgb(5, 5, 5),
         );
 
-        // ECG label
         painter.text(
             rect.min + egui::Vec2::new(10.0, 10.0),
             egui::Align2::LEFT_TOP,
-            "ECG",
+            "HRV",
             egui::FontId::proportional(16.0),
-            self.ecg_color,
+            self.text_color,
         );
 
-        // Calculate real heart rate
-        let heart_rate = self.calculate_heart_rate(samples);
+        let Some(hrv) = hrv else {
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "collecting beats...",
+                egui::FontId::proportional(14.0),
+                self.text_color,
+            );
+            return;
+        };
+
+        let lf_hf = hrv
+            .spectrum
+            .lf_hf_ratio()
+            .map_or("---".to_string(), |ratio| format!("{:.2}", ratio));
+        let lines = [
+            format!("SDNN   {:>6.1} ms", hrv.sdnn),
+            format!("RMSSD  {:>6.1} ms", hrv.rmssd),
+            format!("pNN50  {:>6.1} %", hrv.pnn50),
+            format!("HRV TI {:>6.1}", hrv.triangular_index),
+            format!("LF/HF  {:>6}", lf_hf),
+            format!("SD1/2  {:.0}/{:.0} ms", hrv.sd1, hrv.sd2),
+            format!("{} NN, {} ectopic", hrv.nn_intervals, hrv.ectopic_removed),
+        ];
 
-        painter.text(
-            rect.center() + egui::Vec2::new(0.0, 10.0),
-            egui::Align2::CENTER_CENTER,
-            &format!("{}", heart_rate),
-            egui::FontId::proportional(72.0),
-            self.ecg_color,
-        );
+        let line_height = ((rect.height() - 35.0) / lines.len() as f32).min(18.0);
+        for (i, line) in lines.iter().enumerate() {
+            painter.text(
+                rect.min + egui::Vec2::new(15.0, 32.0 + i as f32 * line_height),
+                egui::Align2::LEFT_TOP,
+                line,
+                egui::FontId::monospace((line_height - 3.0).max(8.0)),
+                self.text_color,
+            );
+        }
+    }
+
+    // Panel background, tinted and outlined in the alarm color while the
+    // reading is outside its limits
+    fn draw_vital_panel_background(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        out_of_limits: Option<AlarmKind>,
+    ) {
+        match out_of_limits {
+            Some(kind) => {
+                let color = Self::alarm_color(kind.priority());
+                painter.rect_filled(
+                    *rect,
+                    egui::Rounding::same(5.0),
+                    egui::Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), 50),
+                );
+                painter.rect_stroke(
+                    *rect,
+                    egui::Rounding::same(5.0),
+                    egui::Stroke::new(2.0, color),
+                );
+            }
+            None => painter.rect_filled(
+                *rect,
+                egui::Rounding::same(5.0),
+                egui::Color32::from_rgb(5, 5, 5),
+            ),
+        }
+    }
+
+    // Upper limit above lower limit under the panel label, as on bedside monitors
+    fn draw_limits(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        limits: &LimitRange,
+        color: egui::Color32,
+    ) {
+        for (i, limit) in [limits.high, limits.low].iter().enumerate() {
+            painter.text(
+                rect.min + egui::Vec2::new(10.0, 34.0 + i as f32 * 16.0),
+                egui::Align2::LEFT_TOP,
+                format!("{:.0}", limit),
+                egui::FontId::proportional(13.0),
+                color,
+            );
+        }
+    }
 
-        // Heart rate bar indicator - dynamic based on HR
+    // Vertical bar filled to `value` over `scale`, with ticks at the alarm limits
+    fn draw_level_bar(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        value: Option<f32>,
+        scale: (f32, f32),
+        limits: &LimitRange,
+        color: egui::Color32,
+    ) {
         let bar_rect = egui::Rect::from_min_size(
             rect.max - egui::Vec2::new(20.0, rect.height() - 20.0),
             egui::Vec2::new(10.0, rect.height() - 40.0),
         );
+        let fraction = |value: f32| ((value - scale.0) / (scale.1 - scale.0)).clamp(0.0, 1.0);
 
-        // Calculate segments to fill based on heart rate (normal range 60-100)
         let segments = 8;
-        let hr_normalized =
-            ((heart_rate - 50).max(0).min(50) as f32 / 50.0 * segments as f32) as usize;
-        let segments_to_fill = hr_normalized.min(segments);
+        let segments_to_fill =
+            ((value.map_or(0.0, fraction) * segments as f32) as usize).min(segments);
 
         let segment_height = bar_rect.height() / segments as f32;
         for i in 0..segments_to_fill {
@@ -388,17 +831,373 @@ impl EcgDisplay {
                 bar_rect.min + egui::Vec2::new(0.0, (segments - 1 - i) as f32 * segment_height),
                 egui::Vec2::new(bar_rect.width(), segment_height - 1.0),
             );
-            painter.rect_filled(segment_rect, egui::Rounding::ZERO, self.ecg_color);
+            painter.rect_filled(segment_rect, egui::Rounding::ZERO, color);
+        }
+
+        for limit in [limits.low, limits.high] {
+            let y = bar_rect.bottom() - fraction(limit) * bar_rect.height();
+            painter.line_segment(
+                [
+                    egui::pos2(bar_rect.left() - 4.0, y),
+                    egui::pos2(bar_rect.right() + 4.0, y),
+                ],
+                egui::Stroke::new(2.0, self.text_color),
+            );
+        }
+    }
+
+    fn draw_hr_panel(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        heart_rate: Option<f32>,
+        limits: &LimitRange,
+        ectopy: &crate::ectopy::EctopySummary,
+    ) {
+        let out_of_limits = heart_rate.and_then(|rate| {
+            if rate < limits.low {
+                Some(AlarmKind::Bradycardia)
+            } else if rate > limits.high {
+                Some(AlarmKind::Tachycardia)
+            } else {
+                None
+            }
+        });
+        self.draw_vital_panel_background(painter, rect, out_of_limits);
+
+        // ECG label
+        painter.text(
+            rect.min + egui::Vec2::new(10.0, 10.0),
+            egui::Align2::LEFT_TOP,
+            "ECG",
+            egui::FontId::proportional(16.0),
+            self.ecg_color,
+        );
+        self.draw_limits(painter, rect, limits, self.ecg_color);
+
+        // Heart rate from R-R intervals; dashes until a rhythm is established
+        painter.text(
+            rect.center() + egui::Vec2::new(0.0, 10.0),
+            egui::Align2::CENTER_CENTER,
+            heart_rate.map_or("---".to_string(), |rate| format!("{:.0}", rate)),
+            egui::FontId::proportional(72.0),
+            self.ecg_color,
+        );
+
+        // Ventricular ectopy counts, in the PVC color once any have been seen
+        painter.text(
+            rect.left_bottom() + egui::Vec2::new(10.0, -8.0),
+            egui::Align2::LEFT_BOTTOM,
+            format!(
+                "PVC/min {}  CPLT {}  BIGEM {}",
+                ectopy.pvcs_per_minute, ectopy.couplets, ectopy.bigeminy_runs
+            ),
+            egui::FontId::proportional(12.0),
+            if ectopy.total_pvcs > 0 {
+                self.beat_color(BeatType::Ventricular)
+            } else {
+                self.text_color
+            },
+        );
+
+        // Heart rate bar indicator over 30-210 BPM
+        self.draw_level_bar(
+            painter,
+            rect,
+            heart_rate,
+            (30.0, 210.0),
+            limits,
+            self.ecg_color,
+        );
+    }
+
+    // Maternal or fetal heart rate in CTG mode
+    fn draw_rate_panel(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        label: &str,
+        rate: Option<f32>,
+        limits: &LimitRange,
+        color: egui::Color32,
+    ) {
+        self.draw_vital_panel_background(painter, rect, None);
+
+        painter.text(
+            rect.min + egui::Vec2::new(10.0, 10.0),
+            egui::Align2::LEFT_TOP,
+            label,
+            egui::FontId::proportional(16.0),
+            color,
+        );
+        self.draw_limits(painter, rect, limits, color);
+
+        painter.text(
+            rect.center() + egui::Vec2::new(0.0, 10.0),
+            egui::Align2::CENTER_CENTER,
+            rate.map_or("---".to_string(), |rate| format!("{:.0}", rate)),
+            egui::FontId::proportional(72.0),
+            color,
+        );
+
+        self.draw_level_bar(painter, rect, rate, FHR_SCALE_BPM, limits, color);
+    }
+
+    // Features of the last ten minutes of FHR trace, with anything outside
+    // the normal pattern in the medium alarm color
+    fn draw_ctg_panel(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        analysis: Option<&CtgAnalysis>,
+    ) {
+        self.draw_vital_panel_background(painter, rect, None);
+
+        painter.text(
+            rect.min + egui::Vec2::new(10.0, 10.0),
+            egui::Align2::LEFT_TOP,
+            "CTG",
+            egui::FontId::proportional(16.0),
+            self.text_color,
+        );
+
+        let Some((analysis, baseline)) =
+            analysis.and_then(|analysis| Some((analysis, analysis.baseline?)))
+        else {
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "collecting trace...",
+                egui::FontId::proportional(14.0),
+                self.text_color,
+            );
+            return;
+        };
+
+        let flag = Self::alarm_color(AlarmPriority::Medium);
+        let color = |abnormal: bool| if abnormal { flag } else { self.text_color };
+        let variability = match analysis.variability {
+            Some(_) if analysis.reduced_variability => "REDUCED".to_string(),
+            Some(bpm) => format!("{:.0} bpm", bpm),
+            None => "---".to_string(),
+        };
+        let lines = [
+            (
+                format!("BASELINE {:>4.0} bpm", baseline),
+                color(!(NORMAL_BASELINE_BPM.0..=NORMAL_BASELINE_BPM.1).contains(&baseline)),
+            ),
+            (
+                format!("VARIAB.  {:>8}", variability),
+                color(analysis.reduced_variability),
+            ),
+            (
+                format!("ACCEL    {:>4}", analysis.accelerations.len()),
+                self.text_color,
+            ),
+            (
+                format!("DECEL    {:>4}", analysis.decelerations.len()),
+                color(!analysis.decelerations.is_empty()),
+            ),
+        ];
+
+        let line_height = ((rect.height() - 35.0) / lines.len() as f32).min(22.0);
+        for (i, (line, color)) in lines.iter().enumerate() {
+            painter.text(
+                rect.min + egui::Vec2::new(15.0, 36.0 + i as f32 * line_height),
+                egui::Align2::LEFT_TOP,
+                line,
+                egui::FontId::monospace((line_height - 4.0).max(8.0)),
+                *color,
+            );
         }
     }
 
-    fn draw_spo2_panel(&self, painter: &egui::Painter, rect: &egui::Rect) {
+    // FHR trend at CTG paper speed with the newest beat at the right edge,
+    // over the shaded normal baseline band. Accelerations (A) and
+    // decelerations (D) are shaded across the strip.
+    fn draw_fhr_strip(&self, painter: &egui::Painter, rect: &egui::Rect, ctg: Option<&CtgStatus>) {
         painter.rect_filled(
             *rect,
-            egui::Rounding::same(5.0),
+            egui::Rounding::ZERO,
             egui::Color32::from_rgb(5, 5, 5),
         );
 
+        let y = |bpm: f32| {
+            rect.bottom()
+                - ((bpm - FHR_SCALE_BPM.0) / (FHR_SCALE_BPM.1 - FHR_SCALE_BPM.0)).clamp(0.0, 1.0)
+                    * rect.height()
+        };
+        painter.rect_filled(
+            egui::Rect::from_x_y_ranges(
+                rect.x_range(),
+                y(NORMAL_BASELINE_BPM.1)..=y(NORMAL_BASELINE_BPM.0),
+            ),
+            egui::Rounding::ZERO,
+            egui::Color32::from_rgb(15, 35, 15),
+        );
+
+        // Every 10 BPM, labelled every 30 as on CTG paper
+        for bpm in (FHR_SCALE_BPM.0 as u32 + 10..=FHR_SCALE_BPM.1 as u32).step_by(10) {
+            let labelled = bpm % 30 == 0;
+            let y = y(bpm as f32);
+            painter.line_segment(
+                [egui::pos2(rect.left(), y), egui::pos2(rect.right(), y)],
+                egui::Stroke::new(
+                    if labelled { 0.8 } else { 0.3 },
+                    egui::Color32::from_rgb(0, 80, 0),
+                ),
+            );
+            if labelled {
+                painter.text(
+                    egui::pos2(rect.left() - 5.0, y),
+                    egui::Align2::RIGHT_CENTER,
+                    bpm.to_string(),
+                    egui::FontId::proportional(11.0),
+                    self.fhr_color,
+                );
+            }
+        }
+
+        painter.text(
+            rect.min - egui::Vec2::new(50.0, 18.0),
+            egui::Align2::LEFT_TOP,
+            "FHR",
+            egui::FontId::proportional(16.0),
+            self.fhr_color,
+        );
+        painter.text(
+            rect.right_top() + egui::Vec2::new(-5.0, 5.0),
+            egui::Align2::RIGHT_TOP,
+            format!("{:.0} cm/min", CTG_PAPER_CM_PER_MIN),
+            egui::FontId::proportional(12.0),
+            self.text_color,
+        );
+
+        let message = match ctg {
+            None => Some("no fetal ECG"),
+            Some(ctg) if ctg.trend.is_empty() => Some("collecting beats..."),
+            Some(_) => None,
+        };
+        if let Some(message) = message {
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                message,
+                egui::FontId::proportional(14.0),
+                self.text_color,
+            );
+            return;
+        }
+        let Some(ctg) = ctg else {
+            return;
+        };
+        let Some(latest) = ctg.trend.last() else {
+            return;
+        };
+
+        let points_per_second = (CTG_PAPER_CM_PER_MIN * POINTS_PER_CM / 60.0) as f64;
+        let x = |time: f64| rect.right() - ((latest.time - time) * points_per_second) as f32;
+        let visible_from = latest.time - rect.width() as f64 / points_per_second;
+
+        // A line every minute of recording time
+        let mut minute = (visible_from.max(0.0) / 60.0).ceil() * 60.0;
+        while minute <= latest.time {
+            painter.line_segment(
+                [
+                    egui::pos2(x(minute), rect.top()),
+                    egui::pos2(x(minute), rect.bottom()),
+                ],
+                egui::Stroke::new(0.5, egui::Color32::from_rgb(0, 80, 0)),
+            );
+            minute += 60.0;
+        }
+
+        let episodes = [
+            (
+                &ctg.analysis.accelerations,
+                "A",
+                egui::Color32::from_rgb(0, 200, 100),
+            ),
+            (
+                &ctg.analysis.decelerations,
+                "D",
+                Self::alarm_color(AlarmPriority::Medium),
+            ),
+        ];
+        for (episodes, letter, color) in episodes {
+            for episode in episodes
+                .iter()
+                .filter(|episode| episode.end >= visible_from)
+            {
+                let span = egui::Rect::from_x_y_ranges(
+                    x(episode.start).max(rect.left())..=x(episode.end),
+                    rect.y_range(),
+                );
+                painter.rect_filled(
+                    span,
+                    egui::Rounding::ZERO,
+                    egui::Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), 40),
+                );
+                painter.text(
+                    span.center_top() + egui::Vec2::new(0.0, 3.0),
+                    egui::Align2::CENTER_TOP,
+                    letter,
+                    egui::FontId::proportional(12.0),
+                    color,
+                );
+            }
+        }
+
+        if let Some(baseline) = ctg.analysis.baseline {
+            painter.extend(egui::Shape::dashed_line(
+                &[
+                    egui::pos2(rect.left(), y(baseline)),
+                    egui::pos2(rect.right(), y(baseline)),
+                ],
+                egui::Stroke::new(1.0, self.text_color),
+                6.0,
+                4.0,
+            ));
+        }
+
+        // The trace breaks where beats were lost for more than a few seconds
+        let visible: Vec<&FhrPoint> = ctg
+            .trend
+            .iter()
+            .filter(|point| point.time >= visible_from)
+            .collect();
+        for pair in visible.windows(2) {
+            if pair[1].time - pair[0].time > 3.0 {
+                continue;
+            }
+            painter.line_segment(
+                [
+                    egui::pos2(x(pair[0].time), y(pair[0].bpm)),
+                    egui::pos2(x(pair[1].time), y(pair[1].bpm)),
+                ],
+                egui::Stroke::new(1.5, self.fhr_color),
+            );
+        }
+    }
+
+    fn draw_spo2_panel(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        spo2: Option<f32>,
+        limits: &LimitRange,
+    ) {
+        let out_of_limits = spo2.and_then(|spo2| {
+            if spo2 < limits.low {
+                Some(AlarmKind::Spo2Low)
+            } else if spo2 > limits.high {
+                Some(AlarmKind::Spo2High)
+            } else {
+                None
+            }
+        });
+        self.draw_vital_panel_background(painter, rect, out_of_limits);
+
         // SpO2 label
         painter.text(
             rect.min + egui::Vec2::new(10.0, 10.0),
@@ -407,13 +1206,13 @@ impl EcgDisplay {
             egui::FontId::proportional(16.0),
             self.spo2_color,
         );
+        self.draw_limits(painter, rect, limits, self.spo2_color);
 
-        // Large SpO2 number - real-time calculation
-        let spo2_value = self.calculate_spo2();
+        // Large SpO2 number
         painter.text(
             rect.center() + egui::Vec2::new(0.0, 10.0),
             egui::Align2::CENTER_CENTER,
-            &format!("{}", spo2_value),
+            spo2.map_or("---".to_string(), |spo2| format!("{:.0}", spo2)),
             egui::FontId::proportional(72.0),
             self.spo2_color,
         );
@@ -427,32 +1226,20 @@ impl EcgDisplay {
             self.spo2_color,
         );
 
-        // SpO2 bar indicator
-        let bar_rect = egui::Rect::from_min_size(
-            rect.max - egui::Vec2::new(20.0, rect.height() - 20.0),
-            egui::Vec2::new(10.0, rect.height() - 40.0),
+        // SpO2 bar indicator over 85-100%, widened to show a lower limit below 85
+        self.draw_level_bar(
+            painter,
+            rect,
+            spo2,
+            (limits.low.min(85.0), 100.0),
+            limits,
+            self.spo2_color,
         );
-
-        let segments = 8;
-        let spo2_value = self.calculate_spo2();
-        // Calculate segments based on SpO2 (85-100% range)
-        let spo2_segments =
-            (((spo2_value - 85).max(0).min(15) as f32 / 15.0) * segments as f32) as usize;
-        let segments_to_fill = spo2_segments.min(segments);
-
-        let segment_height = bar_rect.height() / segments as f32;
-        for i in 0..segments_to_fill {
-            let segment_rect = egui::Rect::from_min_size(
-                bar_rect.min + egui::Vec2::new(0.0, (segments - 1 - i) as f32 * segment_height),
-                egui::Vec2::new(bar_rect.width(), segment_height - 1.0),
-            );
-            painter.rect_filled(segment_rect, egui::Rounding::ZERO, self.spo2_color);
-        }
     }
 
     fn draw_medical_grid(&self, painter: &egui::Painter, rect: &egui::Rect) {
-        let small_grid = 5.0;
-        let large_grid = 25.0;
+        let small_grid = POINTS_PER_MM;
+        let large_grid = 5.0 * POINTS_PER_MM;
 
         // Small grid
         self.draw_grid_lines(
@@ -503,14 +1290,25 @@ impl EcgDisplay {
         }
     }
 
-    fn draw_ecg_label(&self, painter: &egui::Painter, rect: &egui::Rect, label: &str) {
+    // Lead label from the file, without the "ECG" signal type prefix, and
+    // the channel's unit below it
+    fn draw_ecg_label(&self, painter: &egui::Painter, rect: &egui::Rect, channel: &ChannelInfo) {
+        let label = channel.label.trim();
+        let label = label.strip_prefix("ECG ").map_or(label, str::trim_start);
         painter.text(
             rect.min - egui::Vec2::new(50.0, 0.0),
             egui::Align2::LEFT_TOP,
             label,
-            egui::FontId::proportional(16.0),
+            egui::FontId::proportional(if label.len() > 4 { 12.0 } else { 16.0 }),
             self.ecg_color,
         );
+        painter.text(
+            rect.min - egui::Vec2::new(50.0, -18.0),
+            egui::Align2::LEFT_TOP,
+            channel.unit.trim(),
+            egui::FontId::proportional(11.0),
+            self.text_color,
+        );
     }
 
     fn draw_pleth_label(&self, painter: &egui::Painter, rect: &egui::Rect, label: &str) {
@@ -523,61 +1321,131 @@ impl EcgDisplay {
         );
     }
 
+    // 1 mV square pulse left of a trace, showing the gain it is drawn at
+    fn draw_calibration_pulse(
+        &self,
+        painter: &egui::Painter,
+        rect: &egui::Rect,
+        gain_mm_per_mv: f32,
+    ) {
+        let base = rect.center().y + rect.height() * 0.25;
+        let top = (base - gain_mm_per_mv * POINTS_PER_MM).max(rect.top());
+        let left = rect.left() - 14.0;
+        let points = vec![
+            egui::pos2(left, base),
+            egui::pos2(left + 2.0, base),
+            egui::pos2(left + 2.0, top),
+            egui::pos2(left + 8.0, top),
+            egui::pos2(left + 8.0, base),
+            egui::pos2(left + 10.0, base),
+        ];
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, self.ecg_color),
+        ));
+    }
+
+    // `gain` is in points per unit of the channel. The trace runs at paper
+    // speed with the newest sample at the right edge, and is centred on the
+    // middle of its range, so a flat signal stays a flat line.
     fn draw_realistic_ecg<F>(
         &self,
         painter: &egui::Painter,
         rect: &egui::Rect,
-        samples: &VecDeque<EcgSample>,
+        samples_vec: &[&EcgFrame],
+        gain: f32,
         value_extractor: F,
     ) where
-        F: Fn(&EcgSample) -> f32,
+        F: Fn(&EcgFrame) -> f32,
     {
-        if samples.len() < 2 {
+        // Only the newest samples that fit the trace are drawn
+        let points_per_sample = Self::points_per_second() / self.sample_rate;
+        let visible = ((rect.width() / points_per_sample) as usize + 1).min(samples_vec.len());
+        let samples_vec = &samples_vec[samples_vec.len() - visible..];
+        if samples_vec.len() < 2 {
             return;
         }
+        let x = |i: usize| rect.right() - (samples_vec.len() - 1 - i) as f32 * points_per_sample;
 
-        let samples_vec: Vec<&EcgSample> = samples.iter().collect();
-        let baseline_y = rect.center().y;
-        let amplitude_scale = rect.height() / 6.0; // Realistic ECG amplitude
+        let (low, high) = Self::value_range(samples_vec, &value_extractor);
+        let middle = if high >= low { (low + high) / 2.0 } else { 0.0 };
+        let amplitude_scale = gain;
 
         let mut points = Vec::new();
         for (i, sample) in samples_vec.iter().enumerate() {
-            let time_fraction = i as f32 / samples_vec.len() as f32;
-            let x = rect.left() + time_fraction * rect.width();
+            let x = x(i);
             let voltage = value_extractor(sample);
-            let y = baseline_y - voltage * amplitude_scale;
+            let y = rect.center().y - (voltage - middle) * amplitude_scale;
             let y = y.clamp(rect.top(), rect.bottom());
             points.push(egui::pos2(x, y));
         }
 
-        // Draw ECG waveform with realistic thickness
-        if points.len() > 1 {
-            for window in points.windows(2) {
-                painter.line_segment(
-                    [window[0], window[1]],
-                    egui::Stroke::new(2.0, self.ecg_color),
-                );
+        // Ectopic beats are drawn in their own color around the R peak
+        let mut colors = vec![self.ecg_color; samples_vec.len()];
+        let half_width = (ECTOPIC_HIGHLIGHT_S * self.sample_rate) as usize;
+        for (i, sample) in samples_vec.iter().enumerate() {
+            if let Some(beat) = sample.beat.filter(BeatType::is_ectopic) {
+                let end = (i + half_width).min(colors.len() - 1);
+                colors[i.saturating_sub(half_width)..=end].fill(self.beat_color(beat));
+            }
+        }
+
+        // Draw ECG waveform with realistic thickness, leaving a break wherever
+        // the recording has a gap (or the replay wraps around)
+        let max_step = 1.5 / self.sample_rate as f64;
+        for (i, (window, pair)) in points.windows(2).zip(samples_vec.windows(2)).enumerate() {
+            let step = pair[1].timestamp - pair[0].timestamp;
+            if step <= 0.0 || step > max_step {
+                continue;
             }
+            painter.line_segment([window[0], window[1]], egui::Stroke::new(2.0, colors[i]));
         }
 
         // Draw QRS markers
         for (i, sample) in samples_vec.iter().enumerate() {
-            if sample.is_qrs {
-                let time_fraction = i as f32 / samples_vec.len() as f32;
-                let x = rect.left() + time_fraction * rect.width();
+            if let Some(beat) = sample.beat {
+                let x = x(i);
                 painter.line_segment(
                     [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
-                    egui::Stroke::new(1.0, egui::Color32::from_rgb(100, 255, 100)),
+                    egui::Stroke::new(1.0, self.beat_color(beat)),
                 );
             }
         }
     }
 
+    fn points_per_second() -> f32 {
+        PAPER_SPEED_MM_PER_S * POINTS_PER_MM
+    }
+
+    // Lowest and highest finite value of a trace
+    fn value_range<F>(samples: &[&EcgFrame], value_extractor: F) -> (f32, f32)
+    where
+        F: Fn(&EcgFrame) -> f32,
+    {
+        samples
+            .iter()
+            .map(|sample| value_extractor(sample))
+            .filter(|value| value.is_finite())
+            .fold((f32::MAX, f32::MIN), |(low, high), value| {
+                (low.min(value), high.max(value))
+            })
+    }
+
+    fn beat_color(&self, beat: BeatType) -> egui::Color32 {
+        match beat {
+            BeatType::Normal => egui::Color32::from_rgb(100, 255, 100),
+            BeatType::Supraventricular => egui::Color32::from_rgb(255, 200, 0),
+            BeatType::Ventricular => egui::Color32::from_rgb(255, 40, 40),
+            BeatType::Fusion => egui::Color32::from_rgb(255, 100, 255),
+            BeatType::Unclassified => egui::Color32::from_rgb(120, 160, 120),
+        }
+    }
+
     fn draw_pleth_waveform(
         &self,
         painter: &egui::Painter,
         rect: &egui::Rect,
-        _samples: &VecDeque<EcgSample>,
+        _samples: &VecDeque<EcgFrame>,
     ) {
         // Generate realistic plethysmography waveform with variability
         let mut points = Vec::new();
@@ -587,7 +1455,7 @@ impl EcgDisplay {
             .unwrap_or_default()
             .as_secs_f32();
 
-        let heart_rate = 78.0 as f32; // Use average heart rate for pleth
+        let heart_rate = 78.0_f32; // Use average heart rate for pleth
         let pulse_frequency = heart_rate / 60.0; // Hz
 
         for i in 0..num_points {
@@ -602,9 +1470,8 @@ impl EcgDisplay {
             let dicrotic_notch = (phase * 1.5).sin() * 0.1;
             let baseline_drift = (current_time * 0.1).sin() * 0.05;
 
-            let pleth_wave = (systolic_peak + dicrotic_notch + baseline_drift + 0.1)
-                .max(0.0)
-                .min(1.0);
+            let pleth_wave =
+                (systolic_peak + dicrotic_notch + baseline_drift + 0.1).clamp(0.0, 1.0);
             let y = rect.bottom() - pleth_wave * rect.height() * 0.9;
 
             points.push(egui::pos2(x, y));
@@ -620,9 +1487,3 @@ impl EcgDisplay {
         }
     }
 }
-
-impl Default for EcgDisplay {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -0,0 +1,155 @@
+use crate::qrs_parser::BeatType;
+use std::collections::VecDeque;
+
+// PVC rate is the count over the last minute
+const PVC_RATE_WINDOW_S: f64 = 60.0;
+
+// Alternating normal-PVC cycles before a rhythm counts as bigeminy
+const BIGEMINY_CYCLES: usize = 3;
+
+// Gives detected beats the type of the nearest reference label. Detections
+// arrive late and labels may trail them, so a beat is only released once no
+// later label could still match it. Positions are stream sample indices.
+pub struct BeatLabeler {
+    tolerance: u64,
+    labels: VecDeque<(u64, BeatType)>,
+    detections: VecDeque<u64>,
+}
+
+impl BeatLabeler {
+    pub fn new(tolerance: u64) -> Self {
+        Self {
+            tolerance,
+            labels: VecDeque::new(),
+            detections: VecDeque::new(),
+        }
+    }
+
+    pub fn add_label(&mut self, index: u64, beat_type: BeatType) {
+        self.labels.push_back((index, beat_type));
+    }
+
+    pub fn add_detection(&mut self, index: u64) {
+        self.detections.push_back(index);
+    }
+
+    // Next detection that can be classified once samples up to `current` have
+    // been seen. Beats with no label nearby stay unclassified.
+    pub fn next_beat(&mut self, current: u64) -> Option<(u64, BeatType)> {
+        let index = *self.detections.front()?;
+        if current < index + self.tolerance {
+            return None;
+        }
+        self.detections.pop_front();
+
+        // Labels too old for this or any later detection
+        while self
+            .labels
+            .front()
+            .is_some_and(|&(label, _)| label + self.tolerance < index)
+        {
+            self.labels.pop_front();
+        }
+
+        let nearest = self
+            .labels
+            .iter()
+            .enumerate()
+            .filter(|(_, (label, _))| label.abs_diff(index) <= self.tolerance)
+            .min_by_key(|(_, (label, _))| label.abs_diff(index))
+            .map(|(position, _)| position);
+        let beat_type = nearest
+            .and_then(|position| self.labels.remove(position))
+            .map_or(BeatType::Unclassified, |(_, beat_type)| beat_type);
+
+        Some((index, beat_type))
+    }
+}
+
+// Running ventricular ectopy counts for the display
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EctopySummary {
+    pub pvcs_per_minute: usize,
+    pub total_pvcs: usize,
+    pub couplets: usize,
+    pub bigeminy_runs: usize,
+}
+
+pub struct EctopyCounter {
+    // PVC times in seconds within the rate window
+    recent_pvcs: VecDeque<f64>,
+    last_beat: Option<(f64, BeatType)>,
+    // Consecutive PVCs ending at the last beat
+    ventricular_run: usize,
+    // Normal-PVC pairs in the current alternating stretch
+    bigeminy_cycles: usize,
+    summary: EctopySummary,
+}
+
+impl Default for EctopyCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EctopyCounter {
+    pub fn new() -> Self {
+        Self {
+            recent_pvcs: VecDeque::new(),
+            last_beat: None,
+            ventricular_run: 0,
+            bigeminy_cycles: 0,
+            summary: EctopySummary::default(),
+        }
+    }
+
+    pub fn add_beat(&mut self, time: f64, beat_type: BeatType) {
+        // Playback looped back to the start
+        if self.last_beat.is_some_and(|(last, _)| time < last) {
+            *self = Self::new();
+        }
+
+        let ventricular = beat_type == BeatType::Ventricular;
+        let previous_ventricular = self
+            .last_beat
+            .map(|(_, previous)| previous == BeatType::Ventricular);
+
+        if ventricular {
+            self.ventricular_run += 1;
+            self.summary.total_pvcs += 1;
+            self.recent_pvcs.push_back(time);
+        } else {
+            // Exactly two PVCs in a row; three or more is a ventricular run
+            if self.ventricular_run == 2 {
+                self.summary.couplets += 1;
+            }
+            self.ventricular_run = 0;
+        }
+
+        match (previous_ventricular, ventricular) {
+            (Some(false), true) => {
+                self.bigeminy_cycles += 1;
+                if self.bigeminy_cycles == BIGEMINY_CYCLES {
+                    self.summary.bigeminy_runs += 1;
+                }
+            }
+            (Some(true), false) => {}
+            _ => self.bigeminy_cycles = 0,
+        }
+
+        self.last_beat = Some((time, beat_type));
+    }
+
+    // Counts as of recording time `now`
+    pub fn summary(&mut self, now: f64) -> EctopySummary {
+        while self
+            .recent_pvcs
+            .front()
+            .is_some_and(|&pvc| now - pvc > PVC_RATE_WINDOW_S)
+        {
+            self.recent_pvcs.pop_front();
+        }
+        self.summary.pvcs_per_minute = self.recent_pvcs.len();
+        self.summary
+    }
+}
@@ -1,15 +1,19 @@
 use byteorder::{LittleEndian, ReadBytesExt};
+use chrono::{NaiveDate, NaiveDateTime};
+use memmap2::Mmap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 pub struct EdfHeader {
-    pub version: String,
+    pub file_type: FileType,
     pub patient_id: String,
     pub recording_id: String,
     pub start_date: String,
     pub start_time: String,
-    pub header_bytes: u16,
+    pub header_bytes: u32,
     pub data_format: String,
     pub data_records: u32,
     pub record_duration: f64,
@@ -19,208 +23,721 @@ pub struct EdfHeader {
     pub physical_dimensions: Vec<String>,
     pub physical_minimums: Vec<f64>,
     pub physical_maximums: Vec<f64>,
-    pub digital_minimums: Vec<i16>,
-    pub digital_maximums: Vec<i16>,
+    pub digital_minimums: Vec<i32>,
+    pub digital_maximums: Vec<i32>,
     pub prefiltering: Vec<String>,
-    pub samples_per_record: Vec<u16>,
+    pub samples_per_record: Vec<u32>,
     pub reserved: Vec<String>,
 }
 
+impl EdfHeader {
+    // EDF+ and BDF+ files structure the patient and recording fields into subfields
+    pub fn is_plus(&self) -> bool {
+        self.data_format.starts_with("EDF+") || self.data_format.starts_with("BDF+")
+    }
+
+    pub fn patient_info(&self) -> PatientInfo {
+        PatientInfo::parse(&self.patient_id, self.is_plus())
+    }
+
+    pub fn recording_info(&self) -> RecordingInfo {
+        RecordingInfo::parse(
+            &self.recording_id,
+            &self.start_date,
+            &self.start_time,
+            self.is_plus(),
+        )
+    }
+
+    pub fn is_annotation_signal(&self, signal_idx: usize) -> bool {
+        self.signal_labels
+            .get(signal_idx)
+            .is_some_and(|label| label == ANNOTATION_LABEL || label == BDF_ANNOTATION_LABEL)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+// EDF+ patient field: "code sex birthdate name [additional...]", with "X" for
+// unknown subfields and underscores in place of spaces
+#[derive(Debug, Clone, Default)]
+pub struct PatientInfo {
+    pub code: Option<String>,
+    pub sex: Option<Sex>,
+    pub birthdate: Option<NaiveDate>,
+    pub name: Option<String>,
+    pub additional: Vec<String>,
+}
+
+impl PatientInfo {
+    // Plain EDF leaves the field free-form, so the whole text becomes the name
+    pub fn parse(field: &str, edf_plus: bool) -> Self {
+        if !edf_plus {
+            return PatientInfo {
+                name: subfield(field),
+                ..Default::default()
+            };
+        }
+
+        let mut parts = field.split_whitespace();
+        let code = parts.next().and_then(subfield);
+        let sex = match parts.next() {
+            Some("F") | Some("f") => Some(Sex::Female),
+            Some("M") | Some("m") => Some(Sex::Male),
+            _ => None,
+        };
+        let birthdate = parts.next().and_then(parse_edf_date);
+        let name = parts.next().and_then(subfield);
+        let additional = parts.filter_map(subfield).collect();
+
+        PatientInfo {
+            code,
+            sex,
+            birthdate,
+            name,
+            additional,
+        }
+    }
+
+    // Inverse of `parse` for EDF+ files
+    pub fn to_field(&self) -> String {
+        let mut parts = vec![
+            to_subfield(self.code.as_deref()),
+            match self.sex {
+                Some(Sex::Female) => "F".to_string(),
+                Some(Sex::Male) => "M".to_string(),
+                None => "X".to_string(),
+            },
+            format_edf_date(self.birthdate),
+            to_subfield(self.name.as_deref()),
+        ];
+        parts.extend(self.additional.iter().map(|text| to_subfield(Some(text))));
+        parts.join(" ")
+    }
+}
+
+// EDF+ recording field: "Startdate dd-MMM-yyyy admincode technician equipment [additional...]"
+#[derive(Debug, Clone, Default)]
+pub struct RecordingInfo {
+    pub start: Option<NaiveDateTime>,
+    pub admin_code: Option<String>,
+    pub technician: Option<String>,
+    pub equipment: Option<String>,
+    pub additional: Vec<String>,
+}
+
+impl RecordingInfo {
+    // The start timestamp combines the header's dd.mm.yy and hh.mm.ss fields,
+    // taking the four-digit year from the EDF+ Startdate when it is known
+    pub fn parse(field: &str, start_date: &str, start_time: &str, edf_plus: bool) -> Self {
+        let mut info = RecordingInfo {
+            start: parse_header_start(start_date, start_time),
+            ..Default::default()
+        };
+
+        let mut parts = field.split_whitespace();
+        if !edf_plus || parts.next() != Some("Startdate") {
+            info.additional = subfield(field).into_iter().collect();
+            return info;
+        }
+
+        if let Some(date) = parts.next().and_then(parse_edf_date) {
+            info.start = info
+                .start
+                .map(|start| date.and_time(start.time()))
+                .or_else(|| date.and_hms_opt(0, 0, 0));
+        }
+        info.admin_code = parts.next().and_then(subfield);
+        info.technician = parts.next().and_then(subfield);
+        info.equipment = parts.next().and_then(subfield);
+        info.additional = parts.filter_map(subfield).collect();
+
+        info
+    }
+
+    // Inverse of `parse` for EDF+ files; the start time lives in the fixed header fields
+    pub fn to_field(&self) -> String {
+        let mut parts = vec![
+            "Startdate".to_string(),
+            format_edf_date(self.start.map(|start| start.date())),
+            to_subfield(self.admin_code.as_deref()),
+            to_subfield(self.technician.as_deref()),
+            to_subfield(self.equipment.as_deref()),
+        ];
+        parts.extend(self.additional.iter().map(|text| to_subfield(Some(text))));
+        parts.join(" ")
+    }
+}
+
+// "X" marks an unknown EDF+ subfield; underscores stand in for spaces
+fn subfield(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() || text == "X" {
+        None
+    } else {
+        Some(text.replace('_', " "))
+    }
+}
+
+fn to_subfield(text: Option<&str>) -> String {
+    match text.map(str::trim) {
+        Some(text) if !text.is_empty() => text.replace(' ', "_"),
+        _ => "X".to_string(),
+    }
+}
+
+fn format_edf_date(date: Option<NaiveDate>) -> String {
+    date.map_or("X".to_string(), |date| {
+        date.format("%d-%b-%Y").to_string().to_uppercase()
+    })
+}
+
+// EDF+ dates are dd-MMM-yyyy with an English month abbreviation, e.g. 02-AUG-1951
+fn parse_edf_date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text, "%d-%b-%Y").ok()
+}
+
+// Header start date is dd.mm.yy; years 85-99 are 1985-1999, the rest 2000-2084
+fn parse_header_start(start_date: &str, start_time: &str) -> Option<NaiveDateTime> {
+    let mut date = start_date.split('.').map(|part| part.parse::<u32>().ok());
+    let (day, month, year) = (date.next()??, date.next()??, date.next()??);
+    let year = if year >= 85 { 1900 + year } else { 2000 + year };
+
+    let mut time = start_time.split('.').map(|part| part.parse::<u32>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    NaiveDate::from_ymd_opt(year as i32, month, day)?.and_hms_opt(hour, minute, second)
+}
+
+// One channel of a recording, at its own sample rate
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub label: String,
+    pub fs: f64,
+    pub unit: String,
+    pub samples: Vec<f32>,
+}
+
+impl Signal {
+    pub fn duration(&self) -> f64 {
+        if self.fs > 0.0 {
+            self.samples.len() as f64 / self.fs
+        } else {
+            0.0
+        }
+    }
+
+    // Linear-interpolation resampling, good enough for aligning traces on screen
+    pub fn resample(&self, target_fs: f64) -> Vec<f32> {
+        if self.fs <= 0.0 || target_fs <= 0.0 || self.samples.is_empty() {
+            return Vec::new();
+        }
+        if (self.fs - target_fs).abs() < f64::EPSILON {
+            return self.samples.clone();
+        }
+
+        let output_len = (self.duration() * target_fs).round() as usize;
+        let step = self.fs / target_fs;
+        let last = self.samples.len() - 1;
+
+        (0..output_len)
+            .map(|i| {
+                let position = i as f64 * step;
+                let index = (position.floor() as usize).min(last);
+                let next = (index + 1).min(last);
+                let fraction = (position - index as f64) as f32;
+                self.samples[index] + (self.samples[next] - self.samples[index]) * fraction
+            })
+            .collect()
+    }
+}
+
+// European Data Format (16-bit samples) or BioSemi Data Format (24-bit samples)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Edf,
+    Bdf,
+}
+
+impl FileType {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            FileType::Edf => 2,
+            FileType::Bdf => 3,
+        }
+    }
+}
+
+// Labels the EDF+ and BDF+ specs reserve for annotation signals
+pub const ANNOTATION_LABEL: &str = "EDF Annotations";
+pub const BDF_ANNOTATION_LABEL: &str = "BDF Annotations";
+
+// EDF+ Time-stamped Annotation List: "+onset[\x15duration]\x14text\x14...\x14\x00"
+#[derive(Debug, Clone)]
+pub struct Tal {
+    pub onset: f64,
+    pub duration: Option<f64>,
+    pub annotations: Vec<String>,
+}
+
+// Start time of every data record. EDF and EDF+C records are contiguous,
+// while EDF+D records start wherever their timekeeping TAL says they do.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    pub record_onsets: Vec<f64>,
+    pub record_duration: f64,
+}
+
+impl Timeline {
+    // Time in seconds of a sample, for a signal with sample rate `fs`
+    pub fn sample_time(&self, sample_idx: usize, fs: f64) -> f64 {
+        let samples_per_record = self.samples_per_record(fs);
+        if samples_per_record == 0 {
+            return sample_idx as f64 / fs;
+        }
+
+        let record = sample_idx / samples_per_record;
+        let offset = (sample_idx % samples_per_record) as f64 / fs;
+        match self.record_onsets.get(record) {
+            Some(onset) => onset + offset,
+            // Past the last record, keep counting from where it ended
+            None => {
+                self.end()
+                    + (sample_idx - self.record_onsets.len() * samples_per_record) as f64 / fs
+            }
+        }
+    }
+
+    // Index of the sample closest to `time`, or None if `time` falls in a gap
+    pub fn sample_index(&self, time: f64, fs: f64) -> Option<usize> {
+        let samples_per_record = self.samples_per_record(fs);
+        let record = self
+            .record_onsets
+            .iter()
+            .rposition(|&onset| onset <= time + 0.5 / fs)?;
+        let offset = ((time - self.record_onsets[record]) * fs).round().max(0.0) as usize;
+        if offset >= samples_per_record {
+            return None;
+        }
+
+        Some(record * samples_per_record + offset)
+    }
+
+    // (start, end) of every interval not covered by a data record
+    pub fn gaps(&self) -> Vec<(f64, f64)> {
+        self.record_onsets
+            .windows(2)
+            .filter_map(|pair| {
+                let end = pair[0] + self.record_duration;
+                (pair[1] - end > 1e-6).then_some((end, pair[1]))
+            })
+            .collect()
+    }
+
+    pub fn end(&self) -> f64 {
+        self.record_onsets
+            .last()
+            .map_or(0.0, |onset| onset + self.record_duration)
+    }
+
+    fn samples_per_record(&self, fs: f64) -> usize {
+        (fs * self.record_duration).round() as usize
+    }
+}
+
+// A single EDF+ annotation, with its onset relative to the first data sample
+#[derive(Debug, Clone)]
+pub struct EdfAnnotation {
+    pub onset: f64,
+    pub duration: Option<f64>,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub enum EdfError {
+    Io(std::io::Error),
+    // A header field that is missing, unparseable or inconsistent; `offset` is
+    // the field's byte position in the file
+    InvalidField {
+        field: &'static str,
+        signal: Option<usize>,
+        offset: u64,
+        message: String,
+    },
+    FileSize {
+        expected: u64,
+        actual: u64,
+    },
+    Annotations {
+        record: u32,
+        message: String,
+    },
+}
+
+impl fmt::Display for EdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdfError::Io(err) => write!(f, "failed to read EDF file: {}", err),
+            EdfError::InvalidField {
+                field,
+                signal,
+                offset,
+                message,
+            } => {
+                write!(f, "invalid {}", field)?;
+                if let Some(signal) = signal {
+                    write!(f, " of signal {}", signal)?;
+                }
+                write!(f, " at byte {}: {}", offset, message)
+            }
+            EdfError::FileSize { expected, actual } => write!(
+                f,
+                "file is {} bytes but the header describes {} bytes",
+                actual, expected
+            ),
+            EdfError::Annotations { record, message } => {
+                write!(f, "invalid annotations in record {}: {}", record, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EdfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EdfError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for EdfError {
+    fn from(err: std::io::Error) -> Self {
+        EdfError::Io(err)
+    }
+}
+
+// Reads fixed-width ASCII header fields while tracking their byte offset
+struct HeaderFields<'a> {
+    reader: &'a mut BufReader<File>,
+    offset: u64,
+    strict: bool,
+}
+
+impl HeaderFields<'_> {
+    fn read_bytes(&mut self, width: usize) -> Result<Vec<u8>, EdfError> {
+        let mut buffer = vec![0u8; width];
+        self.reader.read_exact(&mut buffer)?;
+        self.offset += width as u64;
+        Ok(buffer)
+    }
+
+    fn read_text(&mut self, width: usize) -> Result<String, EdfError> {
+        let buffer = self.read_bytes(width)?;
+        Ok(String::from_utf8_lossy(&buffer).trim().to_string())
+    }
+
+    fn read_texts(&mut self, width: usize, count: usize) -> Result<Vec<String>, EdfError> {
+        (0..count).map(|_| self.read_text(width)).collect()
+    }
+
+    // Parses a numeric field, substituting `default` unless validating
+    fn read_number<T: std::str::FromStr>(
+        &mut self,
+        field: &'static str,
+        signal: Option<usize>,
+        width: usize,
+        default: T,
+    ) -> Result<T, EdfError> {
+        let offset = self.offset;
+        let text = self.read_text(width)?;
+        match text.parse() {
+            Ok(value) => Ok(value),
+            Err(_) if !self.strict => Ok(default),
+            Err(_) => Err(EdfError::InvalidField {
+                field,
+                signal,
+                offset,
+                message: format!("cannot parse {:?}", text),
+            }),
+        }
+    }
+
+    fn read_numbers<T: std::str::FromStr + Copy>(
+        &mut self,
+        field: &'static str,
+        width: usize,
+        count: usize,
+        default: T,
+    ) -> Result<Vec<T>, EdfError> {
+        (0..count)
+            .map(|signal| self.read_number(field, Some(signal), width, default))
+            .collect()
+    }
+}
+
 pub struct EdfReader {
     file: BufReader<File>,
     header: EdfHeader,
     current_record: u32,
     samples_per_signal: Vec<usize>,
+    start_offset: f64,
 }
 
 impl EdfReader {
+    // Lenient open: header fields that fail to parse fall back to defaults
     pub fn new(filename: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::open(filename, false)?)
+    }
+
+    // Validating open: every header field must parse and agree with the file size
+    pub fn open_validated(filename: &str) -> Result<Self, EdfError> {
+        Self::open(filename, true)
+    }
+
+    fn open(filename: &str, strict: bool) -> Result<Self, EdfError> {
         let file = File::open(filename)?;
+        let file_size = file.metadata()?.len();
         let mut reader = BufReader::new(file);
 
-        let header = Self::read_header(&mut reader)?;
+        let header = Self::read_header(&mut reader, strict, file_size)?;
         let samples_per_signal = header
             .samples_per_record
             .iter()
             .map(|&x| x as usize)
             .collect();
 
-        Ok(EdfReader {
+        let mut edf_reader = EdfReader {
             file: reader,
             header,
-            current_record: 0,
+            current_record: u32::MAX,
             samples_per_signal,
-        })
+            start_offset: 0.0,
+        };
+
+        if strict {
+            let expected = edf_reader.header.header_bytes as u64
+                + edf_reader.header.data_records as u64 * edf_reader.record_bytes() as u64;
+            if expected != file_size {
+                return Err(EdfError::FileSize {
+                    expected,
+                    actual: file_size,
+                });
+            }
+        }
+
+        if edf_reader.has_annotations() && edf_reader.header.data_records > 0 {
+            let first_tals = edf_reader
+                .read_record_annotation_bytes(0)
+                .and_then(|bytes| parse_tals(&bytes));
+            match first_tals {
+                Ok(tals) => {
+                    if let Some(timekeeping) = tals.first() {
+                        edf_reader.start_offset = timekeeping.onset;
+                    }
+                }
+                Err(err) if strict => {
+                    return Err(EdfError::Annotations {
+                        record: 0,
+                        message: err.to_string(),
+                    })
+                }
+                // Lenient mode keeps the data usable and assumes no offset
+                Err(_) => {}
+            }
+        }
+
+        Ok(edf_reader)
     }
 
-    fn read_header(reader: &mut BufReader<File>) -> Result<EdfHeader, Box<dyn std::error::Error>> {
-        let mut buffer = [0u8; 8];
-
-        // Read version (8 bytes)
-        reader.read_exact(&mut buffer)?;
-        let version = String::from_utf8_lossy(&buffer).trim().to_string();
-
-        // Read patient identification (80 bytes)
-        let mut patient_buffer = [0u8; 80];
-        reader.read_exact(&mut patient_buffer)?;
-        let patient_id = String::from_utf8_lossy(&patient_buffer).trim().to_string();
-
-        // Read recording identification (80 bytes)
-        let mut recording_buffer = [0u8; 80];
-        reader.read_exact(&mut recording_buffer)?;
-        let recording_id = String::from_utf8_lossy(&recording_buffer)
-            .trim()
-            .to_string();
-
-        // Read start date (8 bytes)
-        let mut date_buffer = [0u8; 8];
-        reader.read_exact(&mut date_buffer)?;
-        let start_date = String::from_utf8_lossy(&date_buffer).trim().to_string();
-
-        // Read start time (8 bytes)
-        let mut time_buffer = [0u8; 8];
-        reader.read_exact(&mut time_buffer)?;
-        let start_time = String::from_utf8_lossy(&time_buffer).trim().to_string();
-
-        // Read header bytes (8 bytes)
-        let mut header_bytes_buffer = [0u8; 8];
-        reader.read_exact(&mut header_bytes_buffer)?;
-        let binding = String::from_utf8_lossy(&header_bytes_buffer);
-        let header_bytes_str = binding.trim();
-        let header_bytes: u16 = header_bytes_str.parse().unwrap_or(256);
-
-        // Read data format version (44 bytes)
-        let mut format_buffer = [0u8; 44];
-        reader.read_exact(&mut format_buffer)?;
-        let data_format = String::from_utf8_lossy(&format_buffer).trim().to_string();
-
-        // Read number of data records (8 bytes)
-        let mut records_buffer = [0u8; 8];
-        reader.read_exact(&mut records_buffer)?;
-        let binding = String::from_utf8_lossy(&records_buffer);
-        let records_str = binding.trim();
-        let data_records: u32 = records_str.parse().unwrap_or(0);
-
-        // Read duration of a data record (8 bytes)
-        let mut duration_buffer = [0u8; 8];
-        reader.read_exact(&mut duration_buffer)?;
-        let binding = String::from_utf8_lossy(&duration_buffer);
-        let duration_str = binding.trim();
-        let record_duration: f64 = duration_str.parse().unwrap_or(1.0);
-
-        // Read number of signals (4 bytes)
-        let mut signals_buffer = [0u8; 4];
-        reader.read_exact(&mut signals_buffer)?;
-        let binding = String::from_utf8_lossy(&signals_buffer);
-        let signals_str = binding.trim();
-        let signals: u16 = signals_str.parse().unwrap_or(1);
-
-        // Read signal specifications
-        let mut signal_labels = Vec::new();
-        let mut transducer_types = Vec::new();
-        let mut physical_dimensions = Vec::new();
-        let mut physical_minimums = Vec::new();
-        let mut physical_maximums = Vec::new();
-        let mut digital_minimums = Vec::new();
-        let mut digital_maximums = Vec::new();
-        let mut prefiltering = Vec::new();
-        let mut samples_per_record = Vec::new();
-        let mut reserved = Vec::new();
-
-        // Signal labels (16 bytes each)
-        for _ in 0..signals {
-            let mut label_buffer = [0u8; 16];
-            reader.read_exact(&mut label_buffer)?;
-            signal_labels.push(String::from_utf8_lossy(&label_buffer).trim().to_string());
-        }
-
-        // Transducer types (80 bytes each)
-        for _ in 0..signals {
-            let mut transducer_buffer = [0u8; 80];
-            reader.read_exact(&mut transducer_buffer)?;
-            transducer_types.push(
-                String::from_utf8_lossy(&transducer_buffer)
-                    .trim()
-                    .to_string(),
-            );
+    fn read_header(
+        reader: &mut BufReader<File>,
+        strict: bool,
+        file_size: u64,
+    ) -> Result<EdfHeader, EdfError> {
+        let mut fields = HeaderFields {
+            reader,
+            offset: 0,
+            strict,
+        };
+
+        // Read version (8 bytes): "0" for EDF, 0xFF followed by "BIOSEMI" for BDF
+        let buffer = fields.read_bytes(8)?;
+        let file_type = if buffer[0] == 0xFF && &buffer[1..] == b"BIOSEMI" {
+            FileType::Bdf
+        } else {
+            FileType::Edf
+        };
+        let version = match file_type {
+            FileType::Bdf => String::from_utf8_lossy(&buffer[1..]).trim().to_string(),
+            FileType::Edf => String::from_utf8_lossy(&buffer).trim().to_string(),
+        };
+        if strict && file_type == FileType::Edf && version != "0" {
+            return Err(EdfError::InvalidField {
+                field: "version",
+                signal: None,
+                offset: 0,
+                message: format!("expected \"0\" or 0xFF \"BIOSEMI\", found {:?}", version),
+            });
         }
 
-        // Physical dimensions (8 bytes each)
-        for _ in 0..signals {
-            let mut dimension_buffer = [0u8; 8];
-            reader.read_exact(&mut dimension_buffer)?;
-            physical_dimensions.push(
-                String::from_utf8_lossy(&dimension_buffer)
-                    .trim()
-                    .to_string(),
-            );
+        // Patient identification (80 bytes), recording identification (80 bytes),
+        // start date (8 bytes) and start time (8 bytes)
+        let patient_id = fields.read_text(80)?;
+        let recording_id = fields.read_text(80)?;
+        let start_date = fields.read_text(8)?;
+        let start_time = fields.read_text(8)?;
+
+        // Number of bytes in the header (8 bytes)
+        let header_bytes_offset = fields.offset;
+        let header_bytes: u32 = fields.read_number("header bytes", None, 8, 256)?;
+
+        // Data format version (44 bytes)
+        let data_format = fields.read_text(44)?;
+
+        // Number of data records (8 bytes); -1 while a recording is still in
+        // progress, in which case the count comes from the file length below
+        let data_records_offset = fields.offset;
+        let declared_records: i64 = fields.read_number("number of data records", None, 8, -1)?;
+        if strict && (declared_records < -1 || declared_records > u32::MAX as i64) {
+            return Err(EdfError::InvalidField {
+                field: "number of data records",
+                signal: None,
+                offset: data_records_offset,
+                message: format!("must be -1 or a record count, found {}", declared_records),
+            });
         }
 
-        // Physical minimums (8 bytes each)
-        for _ in 0..signals {
-            let mut min_buffer = [0u8; 8];
-            reader.read_exact(&mut min_buffer)?;
-            let binding = String::from_utf8_lossy(&min_buffer);
-            let min_str = binding.trim();
-            physical_minimums.push(min_str.parse().unwrap_or(-2048.0));
-        }
-
-        // Physical maximums (8 bytes each)
-        for _ in 0..signals {
-            let mut max_buffer = [0u8; 8];
-            reader.read_exact(&mut max_buffer)?;
-            let binding = String::from_utf8_lossy(&max_buffer);
-            let max_str = binding.trim();
-            physical_maximums.push(max_str.parse().unwrap_or(2047.0));
-        }
-
-        // Digital minimums (8 bytes each)
-        for _ in 0..signals {
-            let mut min_buffer = [0u8; 8];
-            reader.read_exact(&mut min_buffer)?;
-            let binding = String::from_utf8_lossy(&min_buffer);
-            let min_str = binding.trim();
-            digital_minimums.push(min_str.parse().unwrap_or(-2048));
-        }
-
-        // Digital maximums (8 bytes each)
-        for _ in 0..signals {
-            let mut max_buffer = [0u8; 8];
-            reader.read_exact(&mut max_buffer)?;
-            let binding = String::from_utf8_lossy(&max_buffer);
-            let max_str = binding.trim();
-            digital_maximums.push(max_str.parse().unwrap_or(2047));
-        }
-
-        // Prefiltering (80 bytes each)
-        for _ in 0..signals {
-            let mut prefilter_buffer = [0u8; 80];
-            reader.read_exact(&mut prefilter_buffer)?;
-            prefiltering.push(
-                String::from_utf8_lossy(&prefilter_buffer)
-                    .trim()
-                    .to_string(),
-            );
+        // Duration of a data record in seconds (8 bytes)
+        let duration_offset = fields.offset;
+        let record_duration: f64 = fields.read_number("record duration", None, 8, 1.0)?;
+        if strict && !(record_duration >= 0.0 && record_duration.is_finite()) {
+            return Err(EdfError::InvalidField {
+                field: "record duration",
+                signal: None,
+                offset: duration_offset,
+                message: format!("must be a non-negative number, found {}", record_duration),
+            });
         }
 
-        // Samples per record (8 bytes each)
-        for _ in 0..signals {
-            let mut samples_buffer = [0u8; 8];
-            reader.read_exact(&mut samples_buffer)?;
-            let binding = String::from_utf8_lossy(&samples_buffer);
-            let samples_str = binding.trim();
-            samples_per_record.push(samples_str.parse().unwrap_or(360));
+        // Number of signals (4 bytes)
+        let signals_offset = fields.offset;
+        let signals: u16 = fields.read_number("number of signals", None, 4, 1)?;
+        if strict && signals == 0 {
+            return Err(EdfError::InvalidField {
+                field: "number of signals",
+                signal: None,
+                offset: signals_offset,
+                message: "file declares no signals".to_string(),
+            });
         }
 
-        // Reserved (32 bytes each)
-        for _ in 0..signals {
-            let mut reserved_buffer = [0u8; 32];
-            reader.read_exact(&mut reserved_buffer)?;
-            reserved.push(String::from_utf8_lossy(&reserved_buffer).trim().to_string());
+        let expected_header_bytes = 256 * (signals as u32 + 1);
+        if strict && header_bytes != expected_header_bytes {
+            return Err(EdfError::InvalidField {
+                field: "header bytes",
+                signal: None,
+                offset: header_bytes_offset,
+                message: format!(
+                    "{} signals need a {}-byte header, found {}",
+                    signals, expected_header_bytes, header_bytes
+                ),
+            });
+        }
+
+        // Signal specifications, each field stored for all signals in turn
+        let signals = signals as usize;
+        let signal_labels = fields.read_texts(16, signals)?;
+        let transducer_types = fields.read_texts(80, signals)?;
+        let physical_dimensions = fields.read_texts(8, signals)?;
+        let physical_minimums_offset = fields.offset;
+        let physical_minimums: Vec<f64> =
+            fields.read_numbers("physical minimum", 8, signals, -2048.0)?;
+        let physical_maximums: Vec<f64> =
+            fields.read_numbers("physical maximum", 8, signals, 2047.0)?;
+        let digital_minimums_offset = fields.offset;
+        let digital_minimums: Vec<i32> =
+            fields.read_numbers("digital minimum", 8, signals, -2048)?;
+        let digital_maximums: Vec<i32> =
+            fields.read_numbers("digital maximum", 8, signals, 2047)?;
+        let prefiltering = fields.read_texts(80, signals)?;
+        let samples_per_record_offset = fields.offset;
+        let samples_per_record: Vec<u32> =
+            fields.read_numbers("samples per record", 8, signals, 360)?;
+        let reserved = fields.read_texts(32, signals)?;
+
+        // A signal without samples has no sample rate and breaks record layout
+        if let Some(signal) = samples_per_record.iter().position(|&samples| samples == 0) {
+            return Err(EdfError::InvalidField {
+                field: "samples per record",
+                signal: Some(signal),
+                offset: samples_per_record_offset + signal as u64 * 8,
+                message: "signal has no samples in a data record".to_string(),
+            });
+        }
+
+        let data_records = match u32::try_from(declared_records) {
+            Ok(records) => records,
+            // Count the complete records actually in the file
+            Err(_) => {
+                let record_bytes = samples_per_record.iter().map(|&x| x as u64).sum::<u64>()
+                    * file_type.bytes_per_sample() as u64;
+                let records = file_size
+                    .saturating_sub(header_bytes as u64)
+                    .checked_div(record_bytes)
+                    .unwrap_or(0);
+                records.min(u32::MAX as u64) as u32
+            }
+        };
+
+        if strict {
+            // 16-bit samples for EDF, 24-bit for BDF
+            let digital_limit = match file_type {
+                FileType::Edf => i16::MAX as i32,
+                FileType::Bdf => (1 << 23) - 1,
+            };
+            for signal in 0..signals {
+                let (digital_min, digital_max) =
+                    (digital_minimums[signal], digital_maximums[signal]);
+                if digital_min < -digital_limit - 1 || digital_min >= digital_max {
+                    return Err(EdfError::InvalidField {
+                        field: "digital minimum",
+                        signal: Some(signal),
+                        offset: digital_minimums_offset + signal as u64 * 8,
+                        message: format!(
+                            "{} is out of range or not below the digital maximum {}",
+                            digital_min, digital_max
+                        ),
+                    });
+                }
+                if digital_max > digital_limit {
+                    return Err(EdfError::InvalidField {
+                        field: "digital maximum",
+                        signal: Some(signal),
+                        offset: digital_minimums_offset + (signals + signal) as u64 * 8,
+                        message: format!("{} does not fit in a sample", digital_max),
+                    });
+                }
+                if physical_minimums[signal] == physical_maximums[signal] {
+                    return Err(EdfError::InvalidField {
+                        field: "physical minimum",
+                        signal: Some(signal),
+                        offset: physical_minimums_offset + signal as u64 * 8,
+                        message: format!(
+                            "equals the physical maximum {}",
+                            physical_maximums[signal]
+                        ),
+                    });
+                }
+            }
         }
 
         Ok(EdfHeader {
-            version,
+            file_type,
             patient_id,
             recording_id,
             start_date,
@@ -229,7 +746,7 @@ impl EdfReader {
             data_format,
             data_records,
             record_duration,
-            signals,
+            signals: signals as u16,
             signal_labels,
             transducer_types,
             physical_dimensions,
@@ -243,54 +760,247 @@ impl EdfReader {
         })
     }
 
-    pub fn read_signals(&mut self) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
-        let mut all_samples = Vec::new();
+    // Reads every ordinary signal at its own sample rate and length; EDF+
+    // annotation signals are skipped here and exposed via `read_annotations`
+    pub fn read_channels(&mut self) -> Result<Vec<Signal>, Box<dyn std::error::Error>> {
+        self.read_records(0, self.header.data_records)
+    }
 
-        // Calculate total samples across all records
-        let total_samples_per_signal: usize =
-            self.header.samples_per_record[0] as usize * self.header.data_records as usize;
+    // Reads `duration` seconds of data starting `start` seconds into the data
+    // records. Times count recorded data only, so EDF+D gaps are not included.
+    pub fn read_range(
+        &mut self,
+        start: f64,
+        duration: f64,
+    ) -> Result<Vec<Signal>, Box<dyn std::error::Error>> {
+        let record_duration = self.header.record_duration;
+        if record_duration <= 0.0 || duration <= 0.0 || start < 0.0 {
+            return Ok(self.data_signals());
+        }
 
-        // Initialize signal vectors
-        for _ in 0..self.header.signals {
-            all_samples.push(Vec::with_capacity(total_samples_per_signal));
+        let first_record = (start / record_duration).floor() as u32;
+        let end_record = ((start + duration) / record_duration).ceil() as u32;
+        let mut signals =
+            self.read_records(first_record, end_record.saturating_sub(first_record))?;
+
+        // Trim the partial records at either end
+        let range_offset = start - first_record as f64 * record_duration;
+        for signal in signals.iter_mut() {
+            let skip = ((range_offset * signal.fs).round() as usize).min(signal.samples.len());
+            let keep = (duration * signal.fs).round() as usize;
+            signal.samples.drain(..skip);
+            signal.samples.truncate(keep);
+        }
+
+        Ok(signals)
+    }
+
+    // Reads `count` data records starting at `first_record`, seeking straight
+    // to them so only the requested part of the file is touched
+    pub fn read_records(
+        &mut self,
+        first_record: u32,
+        count: u32,
+    ) -> Result<Vec<Signal>, Box<dyn std::error::Error>> {
+        let first_record = first_record.min(self.header.data_records);
+        let count = count.min(self.header.data_records - first_record);
+
+        let mut signals = self.data_signals();
+        for signal in signals.iter_mut() {
+            signal.samples.reserve(
+                (signal.fs * self.header.record_duration).round() as usize * count as usize,
+            );
         }
 
-        // Seek to start of data (after header)
-        self.file
-            .seek(SeekFrom::Start(self.header.header_bytes as u64))?;
+        // Seek to the first requested record unless the cursor is already there
+        if self.current_record != first_record {
+            let record_bytes = self.record_bytes();
+            self.file.seek(SeekFrom::Start(
+                self.header.header_bytes as u64 + first_record as u64 * record_bytes as u64,
+            ))?;
+            self.current_record = first_record;
+        }
 
         // Read each data record
-        for _record in 0..self.header.data_records {
+        for _record in 0..count {
             // Read samples for each signal in this record
+            let mut output = signals.iter_mut();
             for signal_idx in 0..self.header.signals as usize {
-                let samples_in_record = self.header.samples_per_record[signal_idx] as usize;
+                let samples_in_record = self.samples_per_signal[signal_idx];
+
+                if self.is_annotation_signal(signal_idx) {
+                    self.file
+                        .seek_relative((samples_in_record * self.bytes_per_sample()) as i64)?;
+                    continue;
+                }
+                let signal = output.next().expect("one output per data signal");
 
                 for _sample in 0..samples_in_record {
-                    // Read 16-bit signed integer (little endian)
-                    match self.file.read_i16::<LittleEndian>() {
+                    // Read 16-bit (EDF) or 24-bit (BDF) signed integer (little endian)
+                    let sample = match self.header.file_type {
+                        FileType::Edf => self.file.read_i16::<LittleEndian>().map(i32::from),
+                        FileType::Bdf => self.file.read_i24::<LittleEndian>(),
+                    };
+                    match sample {
                         Ok(digital_value) => {
                             // Convert digital value to physical value
                             let physical_value =
-                                self.digital_to_physical(digital_value, signal_idx);
-                            all_samples[signal_idx].push(physical_value);
+                                Self::digital_to_physical(&self.header, digital_value, signal_idx);
+                            signal.samples.push(physical_value);
                         }
                         Err(_) => {
                             // If we can't read more data, return what we have
-                            return Ok(self.transpose_samples(all_samples));
+                            self.current_record = u32::MAX;
+                            return Ok(signals);
                         }
                     }
                 }
             }
+            self.current_record += 1;
+        }
+
+        Ok(signals)
+    }
+
+    // Every ordinary signal with its label, rate and unit but no samples
+    pub fn data_signals(&self) -> Vec<Signal> {
+        (0..self.header.signals as usize)
+            .filter(|&idx| !self.is_annotation_signal(idx))
+            .map(|signal_idx| Signal {
+                label: self.header.signal_labels[signal_idx].clone(),
+                fs: self.signal_sample_rate(signal_idx),
+                unit: self.header.physical_dimensions[signal_idx].clone(),
+                samples: Vec::new(),
+            })
+            .collect()
+    }
+
+    // EDF+ annotations with onsets relative to the first data sample
+    pub fn read_annotations(&mut self) -> Result<Vec<EdfAnnotation>, Box<dyn std::error::Error>> {
+        let mut annotations = Vec::new();
+
+        for record in self.read_annotation_records()? {
+            // The first TAL of each record opens with an empty timekeeping
+            // annotation, which the filter below drops; any that follow are real
+            for tal in &record {
+                for text in tal.annotations.iter().filter(|text| !text.is_empty()) {
+                    annotations.push(EdfAnnotation {
+                        onset: tal.onset - self.start_offset,
+                        duration: tal.duration,
+                        text: text.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(annotations)
+    }
+
+    // EDF+D files may leave gaps between data records
+    pub fn is_discontinuous(&self) -> bool {
+        self.header.data_format.starts_with("EDF+D") || self.header.data_format.starts_with("BDF+D")
+    }
+
+    // Record start times relative to the first data sample
+    pub fn read_timeline(&mut self) -> Result<Timeline, Box<dyn std::error::Error>> {
+        let record_duration = self.header.record_duration;
+
+        let record_onsets = if self.is_discontinuous() {
+            self.read_annotation_records()?
+                .iter()
+                .enumerate()
+                .map(|(record, tals)| {
+                    tals.first()
+                        .map(|timekeeping| timekeeping.onset - self.start_offset)
+                        .ok_or_else(|| format!("record {} has no timekeeping TAL", record).into())
+                })
+                .collect::<Result<Vec<f64>, Box<dyn std::error::Error>>>()?
+        } else {
+            (0..self.header.data_records)
+                .map(|record| record as f64 * record_duration)
+                .collect()
+        };
+
+        Ok(Timeline {
+            record_onsets,
+            record_duration,
+        })
+    }
+
+    // Seconds between the header start time and the first data sample, taken
+    // from the timekeeping TAL of the first data record
+    pub fn start_offset(&self) -> f64 {
+        self.start_offset
+    }
+
+    pub fn signal_sample_rate(&self, signal_idx: usize) -> f64 {
+        if self.header.record_duration > 0.0 {
+            self.header.samples_per_record[signal_idx] as f64 / self.header.record_duration
+        } else {
+            0.0
         }
+    }
 
-        Ok(self.transpose_samples(all_samples))
+    // Decoded TALs of every data record, one list per record
+    fn read_annotation_records(&mut self) -> Result<Vec<Vec<Tal>>, Box<dyn std::error::Error>> {
+        (0..self.header.data_records)
+            .map(|record| {
+                let bytes = self.read_record_annotation_bytes(record)?;
+                parse_tals(&bytes)
+            })
+            .collect()
     }
 
-    fn digital_to_physical(&self, digital_value: i16, signal_idx: usize) -> f32 {
-        let digital_min = self.header.digital_minimums[signal_idx] as f64;
-        let digital_max = self.header.digital_maximums[signal_idx] as f64;
-        let physical_min = self.header.physical_minimums[signal_idx];
-        let physical_max = self.header.physical_maximums[signal_idx];
+    fn read_record_annotation_bytes(
+        &mut self,
+        record: u32,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let record_bytes = self.record_bytes() as u64;
+        let record_start = self.header.header_bytes as u64 + record as u64 * record_bytes;
+
+        let mut bytes = Vec::new();
+        for signal_idx in 0..self.header.signals as usize {
+            if !self.is_annotation_signal(signal_idx) {
+                continue;
+            }
+
+            // Byte offset of this signal inside the data record
+            let signal_offset = (self.samples_per_signal[..signal_idx].iter().sum::<usize>()
+                * self.bytes_per_sample()) as u64;
+            let mut chunk =
+                vec![0u8; self.samples_per_signal[signal_idx] * self.bytes_per_sample()];
+
+            self.file
+                .seek(SeekFrom::Start(record_start + signal_offset))?;
+            self.file.read_exact(&mut chunk)?;
+            self.current_record = u32::MAX;
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn has_annotations(&self) -> bool {
+        (0..self.header.signals as usize).any(|idx| self.is_annotation_signal(idx))
+    }
+
+    pub fn is_annotation_signal(&self, signal_idx: usize) -> bool {
+        self.header.is_annotation_signal(signal_idx)
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        self.header.file_type.bytes_per_sample()
+    }
+
+    fn record_bytes(&self) -> usize {
+        self.samples_per_signal.iter().sum::<usize>() * self.bytes_per_sample()
+    }
+
+    fn digital_to_physical(header: &EdfHeader, digital_value: i32, signal_idx: usize) -> f32 {
+        let digital_min = header.digital_minimums[signal_idx] as f64;
+        let digital_max = header.digital_maximums[signal_idx] as f64;
+        let physical_min = header.physical_minimums[signal_idx];
+        let physical_max = header.physical_maximums[signal_idx];
 
         let digital_range = digital_max - digital_min;
         let physical_range = physical_max - physical_min;
@@ -305,43 +1015,244 @@ impl EdfReader {
         physical_value as f32
     }
 
-    fn transpose_samples(&self, signal_samples: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        if signal_samples.is_empty() || signal_samples[0].is_empty() {
-            return Vec::new();
-        }
+    // Switches to memory-mapped access to the data records
+    pub fn into_mapped(self) -> Result<MappedEdf, Box<dyn std::error::Error>> {
+        // SAFETY: the map is read-only and callers must not truncate or rewrite
+        // the file while it is mapped, as with any other mmap-based reader
+        let mmap = unsafe { Mmap::map(self.file.get_ref())? };
 
-        let num_samples = signal_samples[0].len();
-        let num_signals = signal_samples.len();
-        let mut transposed = Vec::with_capacity(num_samples);
+        let record_bytes = self.record_bytes();
+        let bytes_per_sample = self.bytes_per_sample();
+        let data_start = self.header.header_bytes as usize;
+        let available_records = mmap
+            .len()
+            .saturating_sub(data_start)
+            .checked_div(record_bytes)
+            .unwrap_or(0);
 
-        for sample_idx in 0..num_samples {
-            let mut sample_vector = Vec::with_capacity(num_signals);
-            for signal_idx in 0..num_signals {
-                if sample_idx < signal_samples[signal_idx].len() {
-                    sample_vector.push(signal_samples[signal_idx][sample_idx]);
-                } else {
-                    sample_vector.push(0.0);
-                }
-            }
-            transposed.push(sample_vector);
+        let mut signal_offsets = Vec::with_capacity(self.samples_per_signal.len());
+        let mut offset = 0;
+        for &samples in &self.samples_per_signal {
+            signal_offsets.push(offset);
+            offset += samples * bytes_per_sample;
         }
 
-        transposed
+        Ok(MappedEdf {
+            records: available_records.min(self.header.data_records as usize),
+            header: self.header,
+            mmap,
+            data_start,
+            record_bytes,
+            bytes_per_sample,
+            signal_offsets,
+        })
+    }
+
+    // Recording details with the start timestamp moved to the first data sample
+    pub fn recording_info(&self) -> RecordingInfo {
+        let mut info = self.header.recording_info();
+        info.start = info
+            .start
+            .map(|start| start + chrono::Duration::microseconds((self.start_offset * 1e6) as i64));
+        info
     }
 
     pub fn get_header(&self) -> &EdfHeader {
         &self.header
     }
 
+    // Highest rate among the data signals, so that aligning them onto one
+    // grid never decimates a channel. Annotation signals carry no samples.
     pub fn get_sample_rate(&self) -> f32 {
-        if self.header.record_duration > 0.0 && !self.header.samples_per_record.is_empty() {
-            self.header.samples_per_record[0] as f32 / self.header.record_duration as f32
+        let fs = self
+            .data_signals()
+            .iter()
+            .map(|signal| signal.fs)
+            .fold(0.0, f64::max);
+        if fs > 0.0 {
+            fs as f32
         } else {
             360.0 // Default ECG sample rate
         }
     }
+}
+
+pub fn parse_tals(bytes: &[u8]) -> Result<Vec<Tal>, Box<dyn std::error::Error>> {
+    let mut tals = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        // Unused bytes at the end of an annotation record are zero-filled
+        if bytes[pos] == 0 {
+            pos += 1;
+            continue;
+        }
+
+        let end = bytes[pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(bytes.len(), |offset| pos + offset);
+        let tal = &bytes[pos..end];
+        pos = end + 1;
+
+        let mut fields = tal.split(|&b| b == 0x14);
+        let timestamp = fields.next().unwrap_or_default();
+
+        let mut time_parts = timestamp.split(|&b| b == 0x15);
+        let onset_text = String::from_utf8_lossy(time_parts.next().unwrap_or_default()).to_string();
+        if !onset_text.starts_with('+') && !onset_text.starts_with('-') {
+            return Err(format!("TAL onset must start with '+' or '-': {:?}", onset_text).into());
+        }
+        let onset: f64 = onset_text
+            .parse()
+            .map_err(|_| format!("invalid TAL onset: {:?}", onset_text))?;
+
+        let duration = match time_parts.next() {
+            Some(text) => {
+                let text = String::from_utf8_lossy(text);
+                Some(
+                    text.parse::<f64>()
+                        .map_err(|_| format!("invalid TAL duration: {:?}", text))?,
+                )
+            }
+            None => None,
+        };
+
+        // Every annotation is terminated by 0x14, so the last field is always empty
+        let mut annotations: Vec<String> = fields
+            .map(|text| String::from_utf8_lossy(text).to_string())
+            .collect();
+        if annotations.last().is_some_and(|text| text.is_empty()) {
+            annotations.pop();
+        }
+
+        tals.push(Tal {
+            onset,
+            duration,
+            annotations,
+        });
+    }
+
+    Ok(tals)
+}
+
+// Resamples every signal to `target_fs` and interleaves them into frames of
+// one value per signal. Signals that end early are padded with 0.0.
+pub fn align_signals(signals: &[Signal], target_fs: f64) -> Vec<Vec<f32>> {
+    let resampled: Vec<Vec<f32>> = signals
+        .iter()
+        .map(|signal| signal.resample(target_fs))
+        .collect();
+    let num_frames = resampled.iter().map(Vec::len).max().unwrap_or(0);
+
+    (0..num_frames)
+        .map(|frame_idx| {
+            resampled
+                .iter()
+                .map(|samples| samples.get(frame_idx).copied().unwrap_or(0.0))
+                .collect()
+        })
+        .collect()
+}
+
+// Memory-mapped EDF file giving zero-copy access to each channel
+pub struct MappedEdf {
+    header: EdfHeader,
+    mmap: Mmap,
+    data_start: usize,
+    record_bytes: usize,
+    bytes_per_sample: usize,
+    records: usize,
+    // Byte offset of every signal inside a data record
+    signal_offsets: Vec<usize>,
+}
+
+impl MappedEdf {
+    pub fn header(&self) -> &EdfHeader {
+        &self.header
+    }
+
+    // Strided view of one signal across every complete data record, or None
+    // when the file has no such signal
+    pub fn channel(&self, signal_idx: usize) -> Option<ChannelView<'_>> {
+        let data_end = self.data_start + self.records * self.record_bytes;
+        Some(ChannelView {
+            data: &self.mmap[self.data_start..data_end],
+            header: &self.header,
+            signal_idx,
+            record_bytes: self.record_bytes,
+            bytes_per_sample: self.bytes_per_sample,
+            signal_offset: *self.signal_offsets.get(signal_idx)?,
+            samples_per_record: *self.header.samples_per_record.get(signal_idx)? as usize,
+            records: self.records,
+        })
+    }
+}
+
+// Raw 16/24-bit samples of one signal, read in place from the mapped data records
+#[derive(Clone, Copy)]
+pub struct ChannelView<'a> {
+    data: &'a [u8],
+    header: &'a EdfHeader,
+    signal_idx: usize,
+    record_bytes: usize,
+    bytes_per_sample: usize,
+    signal_offset: usize,
+    samples_per_record: usize,
+    records: usize,
+}
+
+impl<'a> ChannelView<'a> {
+    pub fn sample_rate(&self) -> f64 {
+        if self.header.record_duration > 0.0 {
+            self.samples_per_record as f64 / self.header.record_duration
+        } else {
+            0.0
+        }
+    }
+
+    // Samples of the signal across the mapped records
+    pub fn len(&self) -> usize {
+        self.records * self.samples_per_record
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Raw values of the samples in `range`, cut short at the end of the signal.
+    // Each sample is located directly, so reads can start anywhere.
+    pub fn digital_range(&self, range: Range<usize>) -> impl Iterator<Item = i32> + 'a {
+        let view = *self;
+        let end = range.end.min(view.len());
+        let start = range.start.min(end);
+        (start..end).map(move |index| {
+            let record = index / view.samples_per_record;
+            let offset = index % view.samples_per_record;
+            let position =
+                record * view.record_bytes + view.signal_offset + offset * view.bytes_per_sample;
+            decode_sample(&view.data[position..position + view.bytes_per_sample])
+        })
+    }
+
+    pub fn physical_range(&self, range: Range<usize>) -> impl Iterator<Item = f32> + 'a {
+        let header = self.header;
+        let signal_idx = self.signal_idx;
+        self.digital_range(range)
+            .map(move |value| EdfReader::digital_to_physical(header, value, signal_idx))
+    }
+
+    pub fn physical_iter(&self) -> impl Iterator<Item = f32> + 'a {
+        self.physical_range(0..self.len())
+    }
+}
 
-    pub fn get_signal_names(&self) -> &Vec<String> {
-        &self.header.signal_labels
+// Little-endian two's complement sample of 2 (EDF) or 3 (BDF) bytes
+fn decode_sample(bytes: &[u8]) -> i32 {
+    match *bytes {
+        [lo, hi] => i16::from_le_bytes([lo, hi]) as i32,
+        // Shift the 24-bit value into the top of an i32 to sign-extend it
+        [lo, mid, hi] => i32::from_le_bytes([0, lo, mid, hi]) >> 8,
+        _ => 0,
     }
 }
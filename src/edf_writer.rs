@@ -0,0 +1,530 @@
+use crate::edf_parser::{
+    EdfAnnotation, EdfHeader, FileType, Signal, ANNOTATION_LABEL, BDF_ANNOTATION_LABEL,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+// Samples reserved per record for the annotation signal (two bytes each in EDF),
+// enough for the timekeeping TAL and a few beat labels
+const ANNOTATION_SAMPLES_PER_RECORD: u32 = 64;
+
+// Byte offset of the "number of data records" field, patched by `finish`
+const DATA_RECORDS_OFFSET: u64 = 236;
+
+// Longest record `header_for` will use, in multiples of the requested duration
+const MAX_RECORD_MULTIPLE: u32 = 1000;
+
+// Writes EDF/EDF+ (or BDF/BDF+) files record by record. Samples are given in
+// physical units and encoded with the header's physical and digital ranges.
+pub struct EdfWriter {
+    file: BufWriter<File>,
+    header: EdfHeader,
+    annotation_signal: Option<usize>,
+    // Physical samples per signal that do not yet fill a whole record
+    pending: Vec<Vec<f32>>,
+    // Annotations not yet written, sorted by onset
+    annotations: VecDeque<EdfAnnotation>,
+    records_written: u32,
+}
+
+impl EdfWriter {
+    // Builds an EDF+C header for the given signals, taking each physical range
+    // from the data. With `with_annotations` an annotation signal is appended.
+    // Records last `record_duration` seconds, or the shortest multiple of it
+    // that holds a whole number of samples of every signal.
+    pub fn header_for(
+        signals: &[Signal],
+        record_duration: f64,
+        with_annotations: bool,
+    ) -> Result<EdfHeader, Box<dyn std::error::Error>> {
+        let record_duration = whole_sample_duration(signals, record_duration)?;
+        let now = chrono::Local::now();
+        let mut header = EdfHeader {
+            file_type: FileType::Edf,
+            // EDF+ uses "X" for unknown subfields
+            patient_id: "X X X X".to_string(),
+            recording_id: format!(
+                "Startdate {} X X X",
+                now.format("%d-%b-%Y").to_string().to_uppercase()
+            ),
+            start_date: now.format("%d.%m.%y").to_string(),
+            start_time: now.format("%H.%M.%S").to_string(),
+            header_bytes: 0,
+            data_format: if with_annotations {
+                "EDF+C".to_string()
+            } else {
+                String::new()
+            },
+            data_records: 0,
+            record_duration,
+            signals: 0,
+            signal_labels: Vec::new(),
+            transducer_types: Vec::new(),
+            physical_dimensions: Vec::new(),
+            physical_minimums: Vec::new(),
+            physical_maximums: Vec::new(),
+            digital_minimums: Vec::new(),
+            digital_maximums: Vec::new(),
+            prefiltering: Vec::new(),
+            samples_per_record: Vec::new(),
+            reserved: Vec::new(),
+        };
+
+        for signal in signals {
+            let (mut physical_min, mut physical_max) = signal
+                .samples
+                .iter()
+                .fold((f64::MAX, f64::MIN), |(min, max), &value| {
+                    (min.min(value as f64), max.max(value as f64))
+                });
+            if physical_min > physical_max {
+                physical_min = -1.0;
+                physical_max = 1.0;
+            } else if physical_min == physical_max {
+                physical_min -= 1.0;
+                physical_max += 1.0;
+            }
+
+            header.signal_labels.push(signal.label.clone());
+            header.transducer_types.push(String::new());
+            header.physical_dimensions.push(signal.unit.clone());
+            header.physical_minimums.push(physical_min);
+            header.physical_maximums.push(physical_max);
+            header.digital_minimums.push(i16::MIN as i32);
+            header.digital_maximums.push(i16::MAX as i32);
+            header.prefiltering.push(String::new());
+            header
+                .samples_per_record
+                .push((signal.fs * record_duration).round() as u32);
+            header.reserved.push(String::new());
+        }
+
+        if with_annotations {
+            header.signal_labels.push(ANNOTATION_LABEL.to_string());
+            header.transducer_types.push(String::new());
+            header.physical_dimensions.push(String::new());
+            header.physical_minimums.push(-1.0);
+            header.physical_maximums.push(1.0);
+            header.digital_minimums.push(i16::MIN as i32);
+            header.digital_maximums.push(i16::MAX as i32);
+            header.prefiltering.push(String::new());
+            header
+                .samples_per_record
+                .push(ANNOTATION_SAMPLES_PER_RECORD);
+            header.reserved.push(String::new());
+        }
+
+        header.signals = header.signal_labels.len() as u16;
+        header.header_bytes = 256 * (header.signals as u32 + 1);
+        Ok(header)
+    }
+
+    // Creates the file and writes its header. The record count is written as
+    // -1 ("unknown") until `finish` patches it.
+    pub fn create(
+        filename: &str,
+        mut header: EdfHeader,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let signals = header.signals as usize;
+        let per_signal_lengths = [
+            header.signal_labels.len(),
+            header.transducer_types.len(),
+            header.physical_dimensions.len(),
+            header.physical_minimums.len(),
+            header.physical_maximums.len(),
+            header.digital_minimums.len(),
+            header.digital_maximums.len(),
+            header.prefiltering.len(),
+            header.samples_per_record.len(),
+            header.reserved.len(),
+        ];
+        if per_signal_lengths.iter().any(|&len| len != signals) {
+            return Err(format!(
+                "header describes {} signals but field lists differ in length",
+                signals
+            )
+            .into());
+        }
+        if header.record_duration <= 0.0 {
+            return Err("record duration must be positive".into());
+        }
+        // Records would never fill, so nothing would ever be written
+        if let Some(idx) = header.samples_per_record.iter().position(|&n| n == 0) {
+            return Err(format!(
+                "signal {} ({}) has no samples per record",
+                idx, header.signal_labels[idx]
+            )
+            .into());
+        }
+
+        // Encode with the ranges exactly as a reader will see them in the
+        // 8-character fields, widened rather than narrowed so no sample clips
+        for value in header.physical_minimums.iter_mut() {
+            *value = format_rounded(*value, f64::floor)?.parse()?;
+        }
+        for value in header.physical_maximums.iter_mut() {
+            *value = format_rounded(*value, f64::ceil)?.parse()?;
+        }
+        header.record_duration = format_number(header.record_duration)?.parse()?;
+
+        let annotation_signal = header
+            .signal_labels
+            .iter()
+            .position(|label| label == ANNOTATION_LABEL || label == BDF_ANNOTATION_LABEL);
+
+        let mut writer = EdfWriter {
+            file: BufWriter::new(File::create(filename)?),
+            header,
+            annotation_signal,
+            pending: vec![Vec::new(); signals],
+            annotations: VecDeque::new(),
+            records_written: 0,
+        };
+        writer.write_header()?;
+
+        Ok(writer)
+    }
+
+    // Appends physical samples, one slice per ordinary (non-annotation) signal
+    // in header order. Complete records are written as soon as they fill.
+    pub fn write_signals(
+        &mut self,
+        signals: &[Vec<f32>],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let data_signals: Vec<usize> = (0..self.header.signals as usize)
+            .filter(|&idx| Some(idx) != self.annotation_signal)
+            .collect();
+        if signals.len() != data_signals.len() {
+            return Err(format!(
+                "expected samples for {} signals, got {}",
+                data_signals.len(),
+                signals.len()
+            )
+            .into());
+        }
+
+        for (&signal_idx, samples) in data_signals.iter().zip(signals) {
+            self.pending[signal_idx].extend_from_slice(samples);
+        }
+
+        while data_signals
+            .iter()
+            .all(|&idx| self.pending[idx].len() >= self.header.samples_per_record[idx] as usize)
+            && !data_signals.is_empty()
+        {
+            self.write_record()?;
+        }
+
+        Ok(())
+    }
+
+    // Queues an annotation for the record that covers its onset, which is
+    // relative to the first sample of the file
+    pub fn add_annotation(
+        &mut self,
+        annotation: EdfAnnotation,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.annotation_signal.is_none() {
+            return Err("file has no annotation signal".into());
+        }
+        let insert_at = self
+            .annotations
+            .partition_point(|queued| queued.onset <= annotation.onset);
+        self.annotations.insert(insert_at, annotation);
+        Ok(())
+    }
+
+    // Pads and writes the final partial record, then records the number of
+    // data records in the header. Returns that count.
+    pub fn finish(mut self) -> Result<u32, Box<dyn std::error::Error>> {
+        while self.pending.iter().any(|samples| !samples.is_empty()) {
+            self.write_record()?;
+        }
+
+        self.file.seek(SeekFrom::Start(DATA_RECORDS_OFFSET))?;
+        write_field(&mut self.file, &self.records_written.to_string(), 8)?;
+        self.file.flush()?;
+
+        if !self.annotations.is_empty() {
+            return Err(format!(
+                "{} annotations fall after the end of the data and were not written",
+                self.annotations.len()
+            )
+            .into());
+        }
+
+        Ok(self.records_written)
+    }
+
+    fn write_header(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let header = &self.header;
+        let out = &mut self.file;
+
+        match header.file_type {
+            FileType::Edf => write_field(out, "0", 8)?,
+            FileType::Bdf => {
+                out.write_all(&[0xFF])?;
+                write_field(out, "BIOSEMI", 7)?;
+            }
+        }
+        write_field(out, &header.patient_id, 80)?;
+        write_field(out, &header.recording_id, 80)?;
+        write_field(out, &header.start_date, 8)?;
+        write_field(out, &header.start_time, 8)?;
+        write_field(out, &(256 * (header.signals as u32 + 1)).to_string(), 8)?;
+        write_field(out, &header.data_format, 44)?;
+        write_field(out, "-1", 8)?;
+        write_field(out, &format_number(header.record_duration)?, 8)?;
+        write_field(out, &header.signals.to_string(), 4)?;
+
+        for label in &header.signal_labels {
+            write_field(out, label, 16)?;
+        }
+        for transducer in &header.transducer_types {
+            write_field(out, transducer, 80)?;
+        }
+        for dimension in &header.physical_dimensions {
+            write_field(out, dimension, 8)?;
+        }
+        for &minimum in &header.physical_minimums {
+            write_field(out, &format_number(minimum)?, 8)?;
+        }
+        for &maximum in &header.physical_maximums {
+            write_field(out, &format_number(maximum)?, 8)?;
+        }
+        for minimum in &header.digital_minimums {
+            write_field(out, &minimum.to_string(), 8)?;
+        }
+        for maximum in &header.digital_maximums {
+            write_field(out, &maximum.to_string(), 8)?;
+        }
+        for prefilter in &header.prefiltering {
+            write_field(out, prefilter, 80)?;
+        }
+        for samples in &header.samples_per_record {
+            write_field(out, &samples.to_string(), 8)?;
+        }
+        for reserved in &header.reserved {
+            write_field(out, reserved, 32)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_record(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let onset = self.records_written as f64 * self.header.record_duration;
+
+        for signal_idx in 0..self.header.signals as usize {
+            let samples_in_record = self.header.samples_per_record[signal_idx] as usize;
+
+            if Some(signal_idx) == self.annotation_signal {
+                let capacity = samples_in_record * self.header.file_type.bytes_per_sample();
+                let bytes = self.annotation_record(onset, capacity)?;
+                self.file.write_all(&bytes)?;
+                continue;
+            }
+
+            // A short final record is padded with zeros
+            let pending = &mut self.pending[signal_idx];
+            let taken = samples_in_record.min(pending.len());
+            let mut samples: Vec<f32> = pending.drain(..taken).collect();
+            samples.resize(samples_in_record, 0.0);
+
+            for value in samples {
+                let digital = physical_to_digital(&self.header, value, signal_idx);
+                match self.header.file_type {
+                    FileType::Edf => self.file.write_i16::<LittleEndian>(digital as i16)?,
+                    FileType::Bdf => self.file.write_i24::<LittleEndian>(digital)?,
+                }
+            }
+        }
+
+        self.records_written += 1;
+        Ok(())
+    }
+
+    // Timekeeping TAL for the record, followed by every queued annotation up
+    // to the end of the record that fits; the rest wait for the next record
+    fn annotation_record(
+        &mut self,
+        onset: f64,
+        capacity: usize,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut bytes = encode_tal(onset, None, &[""]);
+        if bytes.len() > capacity {
+            return Err(format!(
+                "annotation signal too small for the timekeeping TAL ({} bytes)",
+                capacity
+            )
+            .into());
+        }
+
+        let record_end = onset + self.header.record_duration;
+        while let Some(annotation) = self.annotations.front() {
+            if annotation.onset >= record_end {
+                break;
+            }
+            let tal = encode_tal(annotation.onset, annotation.duration, &[&annotation.text]);
+            if tal.len() > capacity - encode_tal(onset, None, &[""]).len() {
+                return Err(format!(
+                    "annotation {:?} does not fit in one record",
+                    annotation.text
+                )
+                .into());
+            }
+            if bytes.len() + tal.len() > capacity {
+                break;
+            }
+            bytes.extend_from_slice(&tal);
+            self.annotations.pop_front();
+        }
+
+        // Unused bytes at the end of an annotation record are zero-filled
+        bytes.resize(capacity, 0);
+        Ok(bytes)
+    }
+}
+
+// Convenience wrapper: writes whole signals and their annotations to a new EDF+ file
+pub fn write_edf(
+    filename: &str,
+    signals: &[Signal],
+    annotations: &[EdfAnnotation],
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let header = EdfWriter::header_for(signals, 1.0, true)?;
+    let mut writer = EdfWriter::create(filename, header)?;
+
+    for annotation in annotations {
+        writer.add_annotation(annotation.clone())?;
+    }
+
+    let samples: Vec<Vec<f32>> = signals
+        .iter()
+        .map(|signal| signal.samples.clone())
+        .collect();
+    writer.write_signals(&samples)?;
+    writer.finish()
+}
+
+// Inverse of the reader's digital-to-physical scaling, clipped to the digital range
+fn physical_to_digital(header: &EdfHeader, physical_value: f32, signal_idx: usize) -> i32 {
+    let digital_min = header.digital_minimums[signal_idx] as f64;
+    let digital_max = header.digital_maximums[signal_idx] as f64;
+    let physical_min = header.physical_minimums[signal_idx];
+    let physical_max = header.physical_maximums[signal_idx];
+
+    let physical_range = physical_max - physical_min;
+    if physical_range == 0.0 {
+        return digital_min as i32;
+    }
+
+    let normalized = (physical_value as f64 - physical_min) / physical_range;
+    let digital_value = digital_min + normalized * (digital_max - digital_min);
+
+    digital_value.round().clamp(digital_min, digital_max) as i32
+}
+
+// "+onset[\x15duration]\x14text\x14...\x00"; a single empty text gives a timekeeping TAL
+pub fn encode_tal(onset: f64, duration: Option<f64>, texts: &[&str]) -> Vec<u8> {
+    let mut tal = format!(
+        "{}{}",
+        if onset < 0.0 { "-" } else { "+" },
+        format_seconds(onset.abs())
+    )
+    .into_bytes();
+    if let Some(duration) = duration {
+        tal.push(0x15);
+        tal.extend_from_slice(format_seconds(duration).as_bytes());
+    }
+    tal.push(0x14);
+    for text in texts {
+        tal.extend_from_slice(text.as_bytes());
+        tal.push(0x14);
+    }
+    tal.push(0);
+    tal
+}
+
+// Seconds rounded to microseconds, without trailing zeros
+fn format_seconds(seconds: f64) -> String {
+    ((seconds * 1e6).round() / 1e6).to_string()
+}
+
+// Shortest multiple of `requested` seconds in which every signal has a whole
+// number of samples, so no signal's rate changes when it is written
+fn whole_sample_duration(
+    signals: &[Signal],
+    requested: f64,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    if requested <= 0.0 {
+        return Err("record duration must be positive".into());
+    }
+    (1..=MAX_RECORD_MULTIPLE)
+        .map(|multiple| requested * multiple as f64)
+        .find(|&duration| {
+            signals.iter().all(|signal| {
+                let samples = signal.fs * duration;
+                samples.round() >= 1.0 && (samples - samples.round()).abs() < 1e-6 * samples
+            })
+        })
+        .ok_or_else(|| {
+            format!(
+                "no record of up to {} s holds a whole number of samples of every signal",
+                requested * MAX_RECORD_MULTIPLE as f64
+            )
+            .into()
+        })
+}
+
+// Shortest decimal form of `value` that fits an 8-character header field;
+// an error when even the rounded integer is too long
+fn format_number(value: f64) -> Result<String, Box<dyn std::error::Error>> {
+    format_rounded(value, f64::round)
+}
+
+// As `format_number`, with digits beyond the field dropped by `round`, e.g.
+// `f64::floor` for a value that must not grow
+fn format_rounded(value: f64, round: fn(f64) -> f64) -> Result<String, Box<dyn std::error::Error>> {
+    if !value.is_finite() {
+        return Err(format!("{} cannot be written to an EDF header", value).into());
+    }
+
+    let plain = value.to_string();
+    if plain.len() <= 8 {
+        return Ok(plain);
+    }
+
+    for decimals in (0..8).rev() {
+        let scale = 10f64.powi(decimals as i32);
+        let text = format!("{:.*}", decimals, round(value * scale) / scale);
+        let text = if text.contains('.') {
+            text.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            text
+        };
+        if text.len() <= 8 {
+            return Ok(text);
+        }
+    }
+
+    Err(format!("{} does not fit in an 8-character EDF header field", value).into())
+}
+
+// Header fields are printable ASCII, left-aligned and padded with spaces
+pub fn write_field(out: &mut impl Write, text: &str, width: usize) -> std::io::Result<()> {
+    let mut bytes: Vec<u8> = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c as u8
+            } else {
+                b'_'
+            }
+        })
+        .take(width)
+        .collect();
+    bytes.resize(width, b' ');
+    out.write_all(&bytes)
+}
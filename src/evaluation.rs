@@ -0,0 +1,243 @@
+use crate::edf_parser::EdfReader;
+use crate::qrs_detector::QrsDetector;
+use crate::qrs_parser::QrsReader;
+use std::path::{Path, PathBuf};
+
+// Match window between a detected and a reference beat, after ANSI/AAMI EC57
+pub const DEFAULT_TOLERANCE: f64 = 0.150;
+
+pub struct EvaluationOptions {
+    // Seconds either side of a reference beat within which a detection counts
+    pub tolerance: f64,
+    // Index among the ordinary (non-annotation) signals to run the detector on
+    pub channel: usize,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: DEFAULT_TOLERANCE,
+            channel: 0,
+        }
+    }
+}
+
+// Beat-by-beat comparison of one record
+#[derive(Debug, Clone, Default)]
+pub struct RecordScore {
+    pub record: String,
+    pub true_positives: usize,
+    pub false_negatives: usize,
+    pub false_positives: usize,
+    // Detected minus reference time of every matched beat, in seconds
+    pub timing_errors: Vec<f64>,
+}
+
+impl RecordScore {
+    pub fn sensitivity(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+
+    pub fn positive_predictivity(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    pub fn f1(&self) -> f64 {
+        ratio(
+            2 * self.true_positives,
+            2 * self.true_positives + self.false_positives + self.false_negatives,
+        )
+    }
+
+    // Mean and standard deviation of the timing error, in seconds
+    pub fn timing_error(&self) -> (f64, f64) {
+        let n = self.timing_errors.len();
+        if n == 0 {
+            return (0.0, 0.0);
+        }
+        let mean = self.timing_errors.iter().sum::<f64>() / n as f64;
+        let variance = self
+            .timing_errors
+            .iter()
+            .map(|error| (error - mean).powi(2))
+            .sum::<f64>()
+            / n as f64;
+        (mean, variance.sqrt())
+    }
+
+    // Gross statistics: every beat of every record counted together
+    pub fn total(scores: &[RecordScore]) -> RecordScore {
+        RecordScore {
+            record: "TOTAL".to_string(),
+            true_positives: scores.iter().map(|score| score.true_positives).sum(),
+            false_negatives: scores.iter().map(|score| score.false_negatives).sum(),
+            false_positives: scores.iter().map(|score| score.false_positives).sum(),
+            timing_errors: scores
+                .iter()
+                .flat_map(|score| score.timing_errors.iter().copied())
+                .collect(),
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+// Matches sorted beat times (seconds) one-to-one. Each reference beat takes the
+// nearest unmatched detection within the tolerance; detections left over are
+// false positives.
+pub fn match_beats(reference: &[f64], detected: &[f64], tolerance: f64) -> RecordScore {
+    let mut score = RecordScore::default();
+    let mut next = 0;
+
+    for &beat in reference {
+        while next < detected.len() && detected[next] < beat - tolerance {
+            score.false_positives += 1;
+            next += 1;
+        }
+
+        if next < detected.len() && detected[next] <= beat + tolerance {
+            // A later detection closer to this beat makes the current one extra
+            while next + 1 < detected.len()
+                && detected[next + 1] <= beat + tolerance
+                && (detected[next + 1] - beat).abs() < (detected[next] - beat).abs()
+            {
+                score.false_positives += 1;
+                next += 1;
+            }
+            score.true_positives += 1;
+            score.timing_errors.push(detected[next] - beat);
+            next += 1;
+        } else {
+            score.false_negatives += 1;
+        }
+    }
+
+    score.false_positives += detected.len() - next;
+    score
+}
+
+// Runs the QRS detector over one channel of an EDF file and scores it against
+// the beats in `annotation_path`
+pub fn evaluate_record(
+    edf_path: &Path,
+    annotation_path: &Path,
+    options: &EvaluationOptions,
+) -> Result<RecordScore, Box<dyn std::error::Error>> {
+    let mut reader = EdfReader::new(&edf_path.to_string_lossy())?;
+    // Reference onsets are recording times, so detections are placed on the
+    // same timeline, gaps and all
+    let timeline = reader.read_timeline()?;
+    let mapped = reader.into_mapped()?;
+    let header = mapped.header();
+    let channel = (0..header.signals as usize)
+        .filter(|&idx| !header.is_annotation_signal(idx))
+        .nth(options.channel)
+        .and_then(|idx| mapped.channel(idx))
+        .ok_or_else(|| format!("no signal {} in {}", options.channel, edf_path.display()))?;
+    if channel.is_empty() {
+        return Err(format!("no complete data records in {}", edf_path.display()).into());
+    }
+    let fs = channel.sample_rate();
+
+    let mut reference: Vec<f64> =
+        QrsReader::load_annotations(&annotation_path.to_string_lossy(), Some(fs))?
+            .iter()
+            .filter(|annotation| annotation.is_beat())
+            .map(|annotation| annotation.time)
+            .collect();
+    reference.sort_by(f64::total_cmp);
+
+    let mut detector = QrsDetector::new(fs as f32);
+    let detected: Vec<f64> = channel
+        .physical_iter()
+        .filter_map(|sample| detector.process(sample))
+        .map(|index| timeline.sample_time(index as usize, fs))
+        .collect();
+
+    let mut score = match_beats(&reference, &detected, options.tolerance);
+    score.record = edf_path.file_stem().map_or_else(
+        || edf_path.display().to_string(),
+        |stem| stem.to_string_lossy().to_string(),
+    );
+    Ok(score)
+}
+
+// Scores every EDF file in `dir` that has a "<name>.edf.qrs" or "<name>.qrs"
+// annotation file next to it, in file name order
+pub fn evaluate_directory(
+    dir: &Path,
+    options: &EvaluationOptions,
+) -> Result<Vec<RecordScore>, Box<dyn std::error::Error>> {
+    let mut edf_paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("edf") || ext.eq_ignore_ascii_case("bdf")
+            })
+        })
+        .collect();
+    edf_paths.sort();
+
+    let mut scores = Vec::new();
+    for edf_path in edf_paths {
+        let Some(annotation_path) = annotation_file_for(&edf_path) else {
+            println!("  skipping {}: no .qrs file", edf_path.display());
+            continue;
+        };
+        match evaluate_record(&edf_path, &annotation_path, options) {
+            Ok(score) => scores.push(score),
+            Err(err) => println!("  skipping {}: {}", edf_path.display(), err),
+        }
+    }
+
+    Ok(scores)
+}
+
+fn annotation_file_for(edf_path: &Path) -> Option<PathBuf> {
+    let mut appended = edf_path.as_os_str().to_owned();
+    appended.push(".qrs");
+    [PathBuf::from(appended), edf_path.with_extension("qrs")]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+// One row per record plus the gross totals
+pub fn summary_table(scores: &[RecordScore]) -> String {
+    let mut table = format!(
+        "{:<12} {:>6} {:>6} {:>6} {:>8} {:>8} {:>8} {:>16}\n",
+        "Record", "TP", "FN", "FP", "Se (%)", "+P (%)", "F1 (%)", "Error (ms)"
+    );
+    for score in scores {
+        table.push_str(&summary_row(score));
+    }
+    table.push_str(&format!("{}\n", "-".repeat(78)));
+    table.push_str(&summary_row(&RecordScore::total(scores)));
+    table
+}
+
+fn summary_row(score: &RecordScore) -> String {
+    let (mean, sd) = score.timing_error();
+    format!(
+        "{:<12} {:>6} {:>6} {:>6} {:>8.2} {:>8.2} {:>8.2} {:>16}\n",
+        score.record,
+        score.true_positives,
+        score.false_negatives,
+        score.false_positives,
+        score.sensitivity() * 100.0,
+        score.positive_predictivity() * 100.0,
+        score.f1() * 100.0,
+        format!("{:.1} ± {:.1}", mean * 1000.0, sd * 1000.0),
+    )
+}
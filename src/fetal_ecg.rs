@@ -0,0 +1,452 @@
+use crate::edf_parser::{EdfReader, Signal};
+use crate::evaluation::{self, RecordScore};
+use crate::filters::Biquad;
+use crate::qrs_detector::QrsDetector;
+use crate::qrs_parser::QrsReader;
+use std::path::Path;
+
+// Pass band for the abdominal leads: removes baseline wander and muscle noise
+// but keeps the sharp, high-frequency fetal QRS
+const PASS_BAND_HZ: (f32, f32) = (1.0, 100.0);
+
+// Maternal beat window around the R peak, long enough to span the T wave
+const MATERNAL_PRE_S: f32 = 0.25;
+const MATERNAL_POST_S: f32 = 0.45;
+
+// Maternal beats averaged into each beat's template, centred on the beat. The
+// fetal heart beats independently, so its complexes average away.
+const TEMPLATE_BEATS: usize = 20;
+
+// Per-lead realignment of maternal beats, and the half width of the QRS
+// region matched while realigning
+const ALIGN_S: f32 = 0.01;
+const ALIGN_QRS_S: f32 = 0.05;
+
+// Distance either side of a detection searched for the true maternal R peak
+const PEAK_SEARCH_S: f32 = 0.03;
+
+// Physiological R-R ranges in seconds (roughly 40-120 and 100-200 BPM)
+const MATERNAL_RR_S: (f64, f64) = (0.5, 1.5);
+const FETAL_RR_S: (f64, f64) = (0.3, 0.6);
+
+// Intervals within this fraction of the median count as regular
+const REGULAR_RR_TOLERANCE: f64 = 0.15;
+
+// Step of the heart rate traces compared during validation, as on a CTG
+const FHR_STEP_S: f64 = 0.25;
+
+// Result of the three extraction stages on the abdominal leads
+pub struct FetalExtraction {
+    pub fs: f32,
+    // Maternal R peaks, as sample indices
+    pub maternal_beats: Vec<usize>,
+    // Abdominal leads after maternal cancellation
+    pub residuals: Vec<Signal>,
+    // Residual lead with the most regular fetal rhythm, and its R peaks
+    pub fetal_channel: usize,
+    pub fetal_beats: Vec<usize>,
+}
+
+impl FetalExtraction {
+    pub fn maternal_times(&self) -> Vec<f64> {
+        to_seconds(&self.maternal_beats, self.fs)
+    }
+
+    pub fn fetal_times(&self) -> Vec<f64> {
+        to_seconds(&self.fetal_beats, self.fs)
+    }
+}
+
+// Cancels the maternal ECG from every abdominal lead by subtracting a running
+// average beat, then detects fetal QRS complexes in the cleanest residual.
+// None when there is no maternal rhythm to cancel.
+pub fn extract(abdominal: &[Signal]) -> Option<FetalExtraction> {
+    let fs = abdominal.first()?.fs as f32;
+    let filtered: Vec<Vec<f32>> = abdominal
+        .iter()
+        .map(|signal| band_pass(&signal.samples, fs))
+        .collect();
+
+    // The maternal heart dominates every lead; combining their energy makes
+    // detection independent of each lead's QRS polarity
+    let len = filtered.iter().map(Vec::len).min().unwrap_or(0);
+    let combined: Vec<f32> = (0..len)
+        .map(|i| filtered.iter().map(|lead| lead[i] * lead[i]).sum::<f32>())
+        .collect();
+    let maternal_beats = refine_beats(&detect(&combined, fs), &combined, fs);
+    if rhythm_score(&maternal_beats, fs, MATERNAL_RR_S) == 0.0 {
+        return None;
+    }
+
+    let residuals: Vec<Signal> = abdominal
+        .iter()
+        .zip(&filtered)
+        .map(|(signal, lead)| Signal {
+            label: signal.label.clone(),
+            fs: signal.fs,
+            unit: signal.unit.clone(),
+            samples: cancel_maternal(lead, &maternal_beats, fs),
+        })
+        .collect();
+
+    let (fetal_channel, fetal_beats) = residuals
+        .iter()
+        .map(|residual| detect(&residual.samples, fs))
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            rhythm_score(a, fs, FETAL_RR_S).total_cmp(&rhythm_score(b, fs, FETAL_RR_S))
+        })?;
+
+    Some(FetalExtraction {
+        fs,
+        maternal_beats,
+        residuals,
+        fetal_channel,
+        fetal_beats,
+    })
+}
+
+// Zero-phase band pass (forward and backward), so beat times are not delayed
+fn band_pass(samples: &[f32], fs: f32) -> Vec<f32> {
+    let mut output = samples.to_vec();
+    for _ in 0..2 {
+        let mut high_pass = Biquad::high_pass(fs, PASS_BAND_HZ.0);
+        let mut low_pass = Biquad::low_pass(fs, PASS_BAND_HZ.1);
+        for sample in output.iter_mut() {
+            *sample = low_pass.process(high_pass.process(*sample));
+        }
+        output.reverse();
+    }
+    output
+}
+
+// Moves each beat to the energy peak nearby and drops the weaker of two
+// beats closer than physiologically possible
+fn refine_beats(beats: &[usize], energy: &[f32], fs: f32) -> Vec<usize> {
+    let search = (PEAK_SEARCH_S * fs) as usize;
+    let min_gap = (MATERNAL_RR_S.0 * 0.6 * fs as f64) as usize;
+
+    let mut refined: Vec<usize> = Vec::with_capacity(beats.len());
+    for &beat in beats {
+        let start = beat.saturating_sub(search);
+        let end = (beat + search + 1).min(energy.len());
+        let Some(peak) = (start..end).max_by(|&a, &b| energy[a].total_cmp(&energy[b])) else {
+            continue;
+        };
+
+        match refined.last_mut() {
+            Some(last) if peak.abs_diff(*last) < min_gap => {
+                if energy[peak] > energy[*last] {
+                    *last = peak;
+                }
+            }
+            _ => refined.push(peak),
+        }
+    }
+    refined
+}
+
+fn detect(signal: &[f32], fs: f32) -> Vec<usize> {
+    let mut detector = QrsDetector::new(fs);
+    signal
+        .iter()
+        .filter_map(|&sample| detector.process(sample))
+        .map(|index| index as usize)
+        .collect()
+}
+
+// Subtracts from each maternal beat the average of its neighbours, scaled by
+// least squares to follow amplitude changes such as breathing
+fn cancel_maternal(lead: &[f32], beats: &[usize], fs: f32) -> Vec<f32> {
+    let pre = (MATERNAL_PRE_S * fs) as usize;
+    let post = (MATERNAL_POST_S * fs) as usize;
+    let window = pre + post;
+    let shift = (ALIGN_S * fs) as usize;
+
+    // Only beats whose whole window is recorded contribute to templates
+    let complete: Vec<usize> = beats
+        .iter()
+        .copied()
+        .filter(|&beat| beat >= pre + shift && beat + post + shift <= lead.len())
+        .collect();
+    if complete.is_empty() {
+        return lead.to_vec();
+    }
+    let complete = align_beats(lead, &complete, (pre, post), shift, fs);
+
+    let mut residual = lead.to_vec();
+    for (k, &beat) in complete.iter().enumerate() {
+        let first = k.saturating_sub(TEMPLATE_BEATS / 2);
+        let last = (first + TEMPLATE_BEATS).min(complete.len());
+        let first = last.saturating_sub(TEMPLATE_BEATS);
+
+        let mut template = vec![0.0f32; window];
+        for &neighbour in &complete[first..last] {
+            for (value, sample) in template.iter_mut().zip(&lead[neighbour - pre..]) {
+                *value += sample;
+            }
+        }
+        let count = (last - first) as f32;
+        template.iter_mut().for_each(|value| *value /= count);
+
+        let segment = &lead[beat - pre..beat + post];
+        let energy: f32 = template.iter().map(|value| value * value).sum();
+        let gain = if energy > 0.0 {
+            segment
+                .iter()
+                .zip(&template)
+                .map(|(x, t)| x * t)
+                .sum::<f32>()
+                / energy
+        } else {
+            0.0
+        };
+
+        // Stop at the next beat's window so overlapping windows are not
+        // subtracted twice
+        let end = complete
+            .get(k + 1)
+            .map_or(beat + post, |&next| (next - pre).clamp(beat, beat + post));
+        for (i, value) in template.iter().enumerate().take(end + pre - beat) {
+            residual[beat - pre + i] = lead[beat - pre + i] - gain * value;
+        }
+    }
+
+    residual
+}
+
+// Shifts each beat by up to `shift` samples to best match this lead's average
+// QRS, since beat times found on the combined leads jitter slightly per lead
+fn align_beats(
+    lead: &[f32],
+    beats: &[usize],
+    (pre, post): (usize, usize),
+    shift: usize,
+    fs: f32,
+) -> Vec<usize> {
+    let half_qrs = ((ALIGN_QRS_S * fs) as usize).min(pre);
+    let mut average = vec![0.0f32; pre + post];
+    for &beat in beats {
+        for (value, sample) in average.iter_mut().zip(&lead[beat - pre..]) {
+            *value += sample / beats.len() as f32;
+        }
+    }
+
+    let qrs = &average[pre - half_qrs..pre + half_qrs];
+    beats
+        .iter()
+        .map(|&beat| {
+            (beat - shift..=beat + shift)
+                .max_by(|&a, &b| {
+                    let correlation = |center: usize| -> f32 {
+                        qrs.iter()
+                            .zip(&lead[center - half_qrs..])
+                            .map(|(t, x)| t * x)
+                            .sum()
+                    };
+                    correlation(a).total_cmp(&correlation(b))
+                })
+                .unwrap_or(beat)
+        })
+        .collect()
+}
+
+// Fraction of R-R intervals close to the median, or zero when the median is
+// outside `range`; higher means a cleaner, more believable rhythm
+fn rhythm_score(beats: &[usize], fs: f32, range: (f64, f64)) -> f64 {
+    let mut intervals: Vec<f64> = beats
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]) as f64 / fs as f64)
+        .collect();
+    if intervals.is_empty() {
+        return 0.0;
+    }
+    intervals.sort_by(f64::total_cmp);
+    let median = intervals[intervals.len() / 2];
+    if !(range.0..=range.1).contains(&median) {
+        return 0.0;
+    }
+
+    intervals
+        .iter()
+        .filter(|&&rr| (rr - median).abs() <= REGULAR_RR_TOLERANCE * median)
+        .count() as f64
+        / intervals.len() as f64
+}
+
+fn to_seconds(beats: &[usize], fs: f32) -> Vec<f64> {
+    beats.iter().map(|&beat| beat as f64 / fs as f64).collect()
+}
+
+// Heart rate in BPM from the median R-R interval
+pub fn median_rate(beat_times: &[f64]) -> Option<f64> {
+    let mut intervals: Vec<f64> = beat_times
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    intervals.sort_by(f64::total_cmp);
+    Some(60.0 / intervals[intervals.len() / 2])
+}
+
+// Beat-to-beat heart rate sampled every FHR_STEP_S, holding each interval's
+// rate until the next beat; None where the interval is implausible
+pub fn heart_rate_trace(beat_times: &[f64], end: f64) -> Vec<Option<f64>> {
+    let steps = (end / FHR_STEP_S).floor() as usize;
+    let mut next = 0;
+    (0..steps)
+        .map(|step| {
+            let time = step as f64 * FHR_STEP_S;
+            while next < beat_times.len() && beat_times[next] <= time {
+                next += 1;
+            }
+            let rr = beat_times.get(next)? - beat_times.get(next.checked_sub(1)?)?;
+            (FETAL_RR_S.0 * 0.5..=FETAL_RR_S.1 * 2.0)
+                .contains(&rr)
+                .then(|| 60.0 / rr)
+        })
+        .collect()
+}
+
+// Agreement of an extracted heart rate with a reference
+pub struct FhrComparison {
+    pub score: RecordScore,
+    // Mean absolute difference of the two traces where both are defined, in BPM
+    pub mean_error: f64,
+    // Share of the reference trace the extracted rate covers within 10 BPM
+    pub within_10_bpm: f64,
+}
+
+pub fn compare(extracted: &[f64], reference: &[f64], end: f64) -> FhrComparison {
+    let score = evaluation::match_beats(reference, extracted, evaluation::DEFAULT_TOLERANCE);
+
+    let extracted_trace = heart_rate_trace(extracted, end);
+    let reference_trace = heart_rate_trace(reference, end);
+    let pairs: Vec<(f64, Option<f64>)> = reference_trace
+        .iter()
+        .zip(&extracted_trace)
+        .filter_map(|(reference, extracted)| reference.map(|reference| (reference, *extracted)))
+        .collect();
+    let errors: Vec<f64> = pairs
+        .iter()
+        .filter_map(|(reference, extracted)| {
+            extracted.map(|extracted| (extracted - reference).abs())
+        })
+        .collect();
+
+    FhrComparison {
+        score,
+        mean_error: if errors.is_empty() {
+            0.0
+        } else {
+            errors.iter().sum::<f64>() / errors.len() as f64
+        },
+        within_10_bpm: if pairs.is_empty() {
+            0.0
+        } else {
+            errors.iter().filter(|&&error| error <= 10.0).count() as f64 / pairs.len() as f64
+        },
+    }
+}
+
+pub struct FetalReport {
+    pub extraction: FetalExtraction,
+    pub duration: f64,
+    // Extracted beats against the QRS detector run on the scalp lead
+    pub vs_direct: Option<FhrComparison>,
+    // Extracted beats against the reference annotations
+    pub vs_annotations: Option<FhrComparison>,
+}
+
+fn is_lead(label: &str, name: &str) -> bool {
+    label.to_lowercase().contains(name)
+}
+
+pub fn is_abdominal(label: &str) -> bool {
+    is_lead(label, "abdom")
+}
+
+// Runs the extraction on the "Abdomen" leads of an EDF file. The labels are
+// checked first and only those leads are read, through the mapped file.
+pub fn extract_file(edf_path: &Path) -> Result<FetalExtraction, Box<dyn std::error::Error>> {
+    let mapped = EdfReader::new(&edf_path.to_string_lossy())?.into_mapped()?;
+    let header = mapped.header();
+    let signals: Vec<Signal> = (0..header.signals as usize)
+        .filter(|&idx| {
+            !header.is_annotation_signal(idx) && is_abdominal(&header.signal_labels[idx])
+        })
+        .filter_map(|idx| {
+            let channel = mapped.channel(idx)?;
+            Some(Signal {
+                label: header.signal_labels[idx].clone(),
+                fs: channel.sample_rate(),
+                unit: header.physical_dimensions[idx].clone(),
+                samples: channel.physical_iter().collect(),
+            })
+        })
+        .collect();
+    if signals.is_empty() {
+        return Err(format!("no abdominal leads in {}", edf_path.display()).into());
+    }
+    extract_channels(&signals, edf_path)
+}
+
+fn extract_channels(
+    signals: &[Signal],
+    edf_path: &Path,
+) -> Result<FetalExtraction, Box<dyn std::error::Error>> {
+    let abdominal: Vec<Signal> = signals
+        .iter()
+        .filter(|signal| is_abdominal(&signal.label))
+        .cloned()
+        .collect();
+    if abdominal.is_empty() {
+        return Err(format!("no abdominal leads in {}", edf_path.display()).into());
+    }
+    extract(&abdominal).ok_or_else(|| "no maternal rhythm found in the abdominal leads".into())
+}
+
+// Runs the extraction and validates it against the "Direct" scalp lead and,
+// when given, an annotation file
+pub fn analyze_file(
+    edf_path: &Path,
+    annotation_path: Option<&Path>,
+) -> Result<FetalReport, Box<dyn std::error::Error>> {
+    let signals = EdfReader::new(&edf_path.to_string_lossy())?.read_channels()?;
+    let extraction = extract_channels(&signals, edf_path)?;
+    let duration = extraction.residuals[0].duration();
+    let fetal_times = extraction.fetal_times();
+
+    let vs_direct = signals
+        .iter()
+        .find(|signal| is_lead(&signal.label, "direct"))
+        .map(|direct| {
+            let fs = direct.fs as f32;
+            let reference = to_seconds(&detect(&band_pass(&direct.samples, fs), fs), fs);
+            compare(&fetal_times, &reference, duration)
+        });
+
+    let vs_annotations = match annotation_path {
+        Some(path) => {
+            let reference: Vec<f64> = QrsReader::load_annotations(
+                &path.to_string_lossy(),
+                Some(extraction.residuals[0].fs),
+            )?
+            .iter()
+            .filter(|annotation| annotation.is_beat())
+            .map(|annotation| annotation.time)
+            .collect();
+            Some(compare(&fetal_times, &reference, duration))
+        }
+        None => None,
+    };
+
+    Ok(FetalReport {
+        extraction,
+        duration,
+        vs_direct,
+        vs_annotations,
+    })
+}
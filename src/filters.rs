@@ -0,0 +1,440 @@
+use crate::EcgFrame;
+use std::collections::VecDeque;
+use std::fmt;
+
+// Width of the mains notches; the comb keeps the same bandwidth at every harmonic
+const NOTCH_Q: f32 = 25.0;
+
+// Harmonics the comb filter removes stay below this fraction of the sample rate
+const COMB_MAX_FRACTION: f32 = 0.45;
+
+// Two-stage median baseline estimator: the first window removes the QRS, the
+// second the P and T waves (de Chazal et al., 2004)
+const MEDIAN_QRS_S: f32 = 0.2;
+const MEDIAN_PT_S: f32 = 0.6;
+
+// Second-order section from the RBJ audio EQ cookbook
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    pub fn low_pass(fs: f32, cutoff: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(fs, cutoff);
+        Self::normalized(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn high_pass(fs: f32, cutoff: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(fs, cutoff);
+        Self::normalized(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    // Rejects a narrow band around `center`, `center / q` wide
+    pub fn notch(fs: f32, center: f32, q: f32) -> Self {
+        let omega = 2.0 * std::f32::consts::PI * center / fs;
+        let (cos, alpha) = (omega.cos(), omega.sin() / (2.0 * q));
+        Self::normalized(1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    // Butterworth response (Q = 1/sqrt(2)); cutoff is kept below Nyquist
+    fn prewarp(fs: f32, cutoff: f32) -> (f32, f32) {
+        let omega = 2.0 * std::f32::consts::PI * cutoff.min(fs * 0.45) / fs;
+        (
+            omega.cos(),
+            omega.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2),
+        )
+    }
+
+    fn normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+// First-order high pass, as the AHA specifies for baseline removal. A
+// biquad's poles sit too close to 1 for f32 at 0.05 Hz, so this runs in f64.
+struct HighPass {
+    alpha: f64,
+    // Previous input and output; None until the first sample
+    state: Option<(f64, f64)>,
+}
+
+impl HighPass {
+    fn new(fs: f32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * std::f64::consts::PI * cutoff as f64);
+        Self {
+            alpha: rc / (rc + 1.0 / fs as f64),
+            state: None,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let x = x as f64;
+        // Start from the first sample's level rather than a step from zero
+        let (x1, y1) = self.state.unwrap_or((x, 0.0));
+        let y = self.alpha * (y1 + x - x1);
+        self.state = Some((x, y));
+        y as f32
+    }
+}
+
+// Running median of the last `width` values
+struct StreamingMedian {
+    width: usize,
+    window: VecDeque<f32>,
+    sorted: Vec<f32>,
+}
+
+impl StreamingMedian {
+    fn new(width: usize) -> Self {
+        Self {
+            width: width.max(1),
+            window: VecDeque::with_capacity(width),
+            sorted: Vec::with_capacity(width),
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        if self.window.len() == self.width {
+            if let Some(oldest) = self.window.pop_front() {
+                let position = self.sorted.partition_point(|&value| value < oldest);
+                self.sorted.remove(position);
+            }
+        }
+        self.window.push_back(x);
+        let position = self.sorted.partition_point(|&value| value < x);
+        self.sorted.insert(position, x);
+        self.sorted[self.sorted.len() / 2]
+    }
+}
+
+// Baseline as the median of medians. Each window is centred on the sample
+// it estimates, so the estimate trails the input by half of both windows.
+struct MedianBaseline {
+    qrs: StreamingMedian,
+    waves: StreamingMedian,
+}
+
+impl MedianBaseline {
+    fn new(fs: f32) -> Self {
+        Self {
+            qrs: StreamingMedian::new(Self::window(fs, MEDIAN_QRS_S)),
+            waves: StreamingMedian::new(Self::window(fs, MEDIAN_PT_S)),
+        }
+    }
+
+    // Odd number of samples, so the window has a centre
+    fn window(fs: f32, seconds: f32) -> usize {
+        (seconds * fs) as usize / 2 * 2 + 1
+    }
+
+    // Samples between an input and the baseline estimate for it
+    fn delay(fs: f32) -> usize {
+        Self::window(fs, MEDIAN_QRS_S) / 2 + Self::window(fs, MEDIAN_PT_S) / 2
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        self.waves.process(self.qrs.process(x))
+    }
+}
+
+// Powerline interference filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MainsFilter {
+    Off,
+    // A single notch at the mains frequency
+    Notch(f32),
+    // Notches at the mains frequency and each of its harmonics
+    Comb(f32),
+}
+
+impl fmt::Display for MainsFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MainsFilter::Off => write!(f, "Off"),
+            MainsFilter::Notch(hz) => write!(f, "Notch {} Hz", hz),
+            MainsFilter::Comb(hz) => write!(f, "Comb {} Hz", hz),
+        }
+    }
+}
+
+// Filters applied to every channel of the sample feed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterSettings {
+    // Cutoffs in Hz; None leaves the band edge open
+    pub high_pass: Option<f32>,
+    pub mains: MainsFilter,
+    pub low_pass: Option<f32>,
+    // Subtract the median baseline, which follows wander without the low
+    // frequency distortion of a high pass
+    pub median_baseline: bool,
+}
+
+impl FilterSettings {
+    pub const HIGH_PASS_OPTIONS: [Option<f32>; 3] = [None, Some(0.05), Some(0.5)];
+    pub const MAINS_OPTIONS: [MainsFilter; 5] = [
+        MainsFilter::Off,
+        MainsFilter::Notch(50.0),
+        MainsFilter::Notch(60.0),
+        MainsFilter::Comb(50.0),
+        MainsFilter::Comb(60.0),
+    ];
+    pub const LOW_PASS_OPTIONS: [Option<f32>; 4] = [None, Some(40.0), Some(100.0), Some(150.0)];
+
+    // Pass band and mains filter, e.g. "0.5-40 Hz  NOTCH 50"
+    pub fn summary(&self) -> String {
+        let low = match (self.median_baseline, self.high_pass) {
+            (true, _) => "MEDIAN".to_string(),
+            (false, Some(hz)) => hz.to_string(),
+            (false, None) => "DC".to_string(),
+        };
+        let high = self
+            .low_pass
+            .map_or("FULL".to_string(), |hz| format!("{} Hz", hz));
+        let mains = match self.mains {
+            MainsFilter::Off => String::new(),
+            mains => format!(
+                "  {}",
+                mains.to_string().to_uppercase().trim_end_matches(" HZ")
+            ),
+        };
+        format!("{}-{}{}", low, high, mains)
+    }
+}
+
+// Display modes, each with its own filters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    // Narrow band for a steady bedside trace
+    Monitor,
+    // The AHA diagnostic band, 0.05-150 Hz
+    Diagnostic,
+    // Baseline held by the median estimator so ST levels are not distorted
+    St,
+}
+
+impl FilterMode {
+    pub const OPTIONS: [FilterMode; 3] =
+        [FilterMode::Monitor, FilterMode::Diagnostic, FilterMode::St];
+
+    pub fn default_settings(&self) -> FilterSettings {
+        // r01 and most recordings here come from 50 Hz mains
+        match self {
+            FilterMode::Monitor => FilterSettings {
+                high_pass: Some(0.5),
+                mains: MainsFilter::Notch(50.0),
+                low_pass: Some(40.0),
+                median_baseline: false,
+            },
+            FilterMode::Diagnostic => FilterSettings {
+                high_pass: Some(0.05),
+                mains: MainsFilter::Notch(50.0),
+                low_pass: Some(150.0),
+                median_baseline: false,
+            },
+            FilterMode::St => FilterSettings {
+                high_pass: None,
+                mains: MainsFilter::Notch(50.0),
+                low_pass: Some(40.0),
+                median_baseline: true,
+            },
+        }
+    }
+}
+
+impl fmt::Display for FilterMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FilterMode::Monitor => "Monitor",
+            FilterMode::Diagnostic => "Diagnostic",
+            FilterMode::St => "ST",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The selected mode and the filters of every mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterConfig {
+    pub mode: FilterMode,
+    pub settings: [FilterSettings; 3],
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            mode: FilterMode::Monitor,
+            settings: FilterMode::OPTIONS.map(|mode| mode.default_settings()),
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn active(&self) -> FilterSettings {
+        self.settings[self.mode as usize]
+    }
+
+    pub fn active_mut(&mut self) -> &mut FilterSettings {
+        &mut self.settings[self.mode as usize]
+    }
+
+    // Header text, e.g. "MONITOR 0.5-40 Hz  NOTCH 50"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {}",
+            self.mode.to_string().to_uppercase(),
+            self.active().summary()
+        )
+    }
+}
+
+struct ChannelFilter {
+    high_pass: Option<HighPass>,
+    mains: Vec<Biquad>,
+    low_pass: Option<Biquad>,
+    median: Option<MedianBaseline>,
+}
+
+impl ChannelFilter {
+    fn new(settings: &FilterSettings, fs: f32) -> Self {
+        let mains = match settings.mains {
+            MainsFilter::Off => Vec::new(),
+            MainsFilter::Notch(hz) => vec![Biquad::notch(fs, hz, NOTCH_Q)],
+            MainsFilter::Comb(hz) => (1..)
+                .map(|harmonic| harmonic as f32)
+                .take_while(|&harmonic| harmonic * hz < fs * COMB_MAX_FRACTION)
+                .map(|harmonic| Biquad::notch(fs, harmonic * hz, harmonic * NOTCH_Q))
+                .collect(),
+        };
+
+        Self {
+            high_pass: settings.high_pass.map(|hz| HighPass::new(fs, hz)),
+            mains,
+            low_pass: settings.low_pass.map(|hz| Biquad::low_pass(fs, hz)),
+            median: settings.median_baseline.then(|| MedianBaseline::new(fs)),
+        }
+    }
+
+    // The filtered value, and the baseline estimate for the value `delay`
+    // samples back when the median estimator is on
+    fn process(&mut self, x: f32) -> (f32, Option<f32>) {
+        let mut y = x;
+        if let Some(high_pass) = &mut self.high_pass {
+            y = high_pass.process(y);
+        }
+        for notch in &mut self.mains {
+            y = notch.process(y);
+        }
+        if let Some(low_pass) = &mut self.low_pass {
+            y = low_pass.process(y);
+        }
+        let baseline = self.median.as_mut().map(|median| median.process(y));
+        (y, baseline)
+    }
+}
+
+// Streaming filter chain over the sample feed. With the median baseline on,
+// whole frames are held back until their baseline is known, so beat marks
+// and timestamps stay with their samples.
+pub struct FilterChain {
+    settings: FilterSettings,
+    channels: Vec<ChannelFilter>,
+    delay: usize,
+    pending: VecDeque<EcgFrame>,
+}
+
+impl FilterChain {
+    pub fn new(settings: FilterSettings, fs: f32, channels: usize) -> Self {
+        Self {
+            settings,
+            channels: (0..channels)
+                .map(|_| ChannelFilter::new(&settings, fs))
+                .collect(),
+            delay: if settings.median_baseline {
+                MedianBaseline::delay(fs)
+            } else {
+                0
+            },
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &FilterSettings {
+        &self.settings
+    }
+
+    // Frames held back for the median baseline, oldest first
+    pub fn pending_mut(&mut self) -> &mut VecDeque<EcgFrame> {
+        &mut self.pending
+    }
+
+    // Releases the held-back frames as they are, without their baseline
+    // removed, when the chain is replaced
+    pub fn flush(&mut self) -> impl Iterator<Item = EcgFrame> + '_ {
+        self.pending.drain(..)
+    }
+
+    // The next filtered frame, or None while the median estimator fills
+    pub fn process(&mut self, mut frame: EcgFrame) -> Option<EcgFrame> {
+        let mut baselines = Vec::new();
+        for (value, filter) in frame.values.iter_mut().zip(&mut self.channels) {
+            let (filtered, baseline) = filter.process(*value);
+            *value = filtered;
+            baselines.push(baseline);
+        }
+
+        self.pending.push_back(frame);
+        if self.pending.len() <= self.delay {
+            return None;
+        }
+        let mut frame = self.pending.pop_front()?;
+        for (value, baseline) in frame.values.iter_mut().zip(baselines) {
+            if let Some(baseline) = baseline {
+                *value -= baseline;
+            }
+        }
+        Some(frame)
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+// Intervals outside 0.2-6 s (300-10 BPM) are double detections or gaps in the data
+const MIN_RR: f64 = 0.2;
+const MAX_RR: f64 = 6.0;
+
+// Once the current interval runs this much longer than the average, it is
+// counted so the rate falls while beats are missing
+const OVERDUE_RR: f64 = 1.5;
+
+// How many R-R intervals the displayed rate is averaged over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HrAveraging {
+    Beats(usize),
+    Seconds(f64),
+}
+
+impl HrAveraging {
+    pub const OPTIONS: [HrAveraging; 5] = [
+        HrAveraging::Beats(4),
+        HrAveraging::Beats(8),
+        HrAveraging::Beats(16),
+        HrAveraging::Seconds(5.0),
+        HrAveraging::Seconds(10.0),
+    ];
+}
+
+impl Default for HrAveraging {
+    fn default() -> Self {
+        HrAveraging::Beats(8)
+    }
+}
+
+impl fmt::Display for HrAveraging {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HrAveraging::Beats(beats) => write!(f, "{} beats", beats),
+            HrAveraging::Seconds(seconds) => write!(f, "{} s", seconds),
+        }
+    }
+}
+
+// Heart rate from R-peak times in recording time, so playback speed and
+// frame rate have no effect on the reading
+pub struct HeartRateMeter {
+    averaging: HrAveraging,
+    // Recent R-peak times in seconds, oldest first
+    beats: VecDeque<f64>,
+}
+
+impl HeartRateMeter {
+    pub fn new(averaging: HrAveraging) -> Self {
+        Self {
+            averaging,
+            beats: VecDeque::new(),
+        }
+    }
+
+    pub fn set_averaging(&mut self, averaging: HrAveraging) {
+        self.averaging = averaging;
+    }
+
+    pub fn add_beat(&mut self, time: f64) {
+        if let Some(&last) = self.beats.back() {
+            let interval = time - last;
+            if (0.0..MIN_RR).contains(&interval) {
+                return;
+            }
+            // Time went backwards (playback looped) or jumped over a gap
+            if !(0.0..=MAX_RR).contains(&interval) {
+                self.beats.clear();
+            }
+        }
+
+        self.beats.push_back(time);
+        // Enough history for the widest averaging option
+        while self.beats.len() > 64 || time - self.beats.front().copied().unwrap_or(time) > 60.0 {
+            self.beats.pop_front();
+        }
+    }
+
+    // Beats per minute at recording time `now`, or None without a rhythm
+    pub fn rate(&self, now: f64) -> Option<f32> {
+        let last = *self.beats.back()?;
+        let since_last = now - last;
+        if !(0.0..=MAX_RR).contains(&since_last) {
+            return None;
+        }
+
+        let intervals: Vec<f64> = self
+            .beats
+            .iter()
+            .zip(self.beats.iter().skip(1))
+            .map(|(a, b)| b - a)
+            .collect();
+
+        let averaged: &[f64] = match self.averaging {
+            HrAveraging::Beats(beats) => &intervals[intervals.len().saturating_sub(beats)..],
+            HrAveraging::Seconds(seconds) => {
+                // Intervals ending inside the window, and always at least the latest one
+                let ending_before = self
+                    .beats
+                    .iter()
+                    .skip(1)
+                    .take_while(|&&beat| beat < now - seconds)
+                    .count();
+                &intervals[ending_before.min(intervals.len().saturating_sub(1))..]
+            }
+        };
+        if averaged.is_empty() {
+            return None;
+        }
+
+        let mut total: f64 = averaged.iter().sum();
+        let mut count = averaged.len();
+        if since_last > OVERDUE_RR * total / count as f64 {
+            total += since_last;
+            count += 1;
+        }
+
+        Some((60.0 * count as f64 / total) as f32)
+    }
+}
@@ -0,0 +1,218 @@
+// Heart rate variability from beat times, following the 1996 Task Force of the
+// ESC/NASPE standards for time-domain, frequency-domain and Poincaré measures
+
+// Physiologically plausible R-R range; anything else is a missed or extra beat
+const MIN_RR: f64 = 0.3;
+const MAX_RR: f64 = 2.0;
+
+// An interval more than 20% away from the median of its neighbours is ectopic
+const ECTOPIC_TOLERANCE: f64 = 0.2;
+const ECTOPIC_NEIGHBOURS: usize = 5;
+
+// Histogram bin of the triangular index, 1/128 s as in the standard
+const HISTOGRAM_BIN: f64 = 1.0 / 128.0;
+
+// Frequency bands in Hz
+const VLF_BAND: (f64, f64) = (0.0033, 0.04);
+const LF_BAND: (f64, f64) = (0.04, 0.15);
+const HF_BAND: (f64, f64) = (0.15, 0.4);
+const FREQUENCY_STEP: f64 = 0.001;
+
+// Fewer intervals than this give meaningless statistics
+const MIN_INTERVALS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct HrvMetrics {
+    pub beats: usize,
+    pub nn_intervals: usize,
+    pub ectopic_removed: usize,
+    // Time domain, in milliseconds except pNN50 (%) and the triangular index
+    pub mean_nn: f64,
+    pub sdnn: f64,
+    pub rmssd: f64,
+    pub pnn50: f64,
+    pub triangular_index: f64,
+    // Poincaré plot axes, in milliseconds
+    pub sd1: f64,
+    pub sd2: f64,
+    pub spectrum: SpectralPower,
+}
+
+// Lomb–Scargle band powers in ms²
+#[derive(Debug, Clone)]
+pub struct SpectralPower {
+    pub vlf: f64,
+    pub lf: f64,
+    pub hf: f64,
+}
+
+impl SpectralPower {
+    pub fn lf_hf_ratio(&self) -> Option<f64> {
+        (self.hf > 0.0).then(|| self.lf / self.hf)
+    }
+}
+
+// One R-R interval ending at `time`, both in seconds
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    time: f64,
+    rr: f64,
+}
+
+// Computes HRV from sorted beat times in seconds; None with too few normal intervals
+pub fn analyze(beat_times: &[f64]) -> Option<HrvMetrics> {
+    let intervals: Vec<Interval> = beat_times
+        .windows(2)
+        .map(|pair| Interval {
+            time: pair[1],
+            rr: pair[1] - pair[0],
+        })
+        .collect();
+    let normal = filter_ectopic(&intervals);
+    if normal.len() < MIN_INTERVALS {
+        return None;
+    }
+
+    let rr: Vec<f64> = normal.iter().map(|interval| interval.rr * 1000.0).collect();
+    let mean_nn = rr.iter().sum::<f64>() / rr.len() as f64;
+    let sdnn =
+        (rr.iter().map(|x| (x - mean_nn).powi(2)).sum::<f64>() / (rr.len() - 1) as f64).sqrt();
+
+    // Successive differences only between intervals that were adjacent in the
+    // recording, so a removed ectopic beat doesn't create a false jump
+    let differences: Vec<f64> = normal
+        .windows(2)
+        .filter(|pair| (pair[1].time - pair[1].rr - pair[0].time).abs() < 1e-6)
+        .map(|pair| (pair[1].rr - pair[0].rr) * 1000.0)
+        .collect();
+    let rmssd = if differences.is_empty() {
+        0.0
+    } else {
+        (differences.iter().map(|d| d * d).sum::<f64>() / differences.len() as f64).sqrt()
+    };
+    let pnn50 = if differences.is_empty() {
+        0.0
+    } else {
+        100.0 * differences.iter().filter(|d| d.abs() > 50.0).count() as f64
+            / differences.len() as f64
+    };
+
+    // SD1 is the spread across the identity line, SD2 along it
+    let sd1 = rmssd / std::f64::consts::SQRT_2;
+    let sd2 = (2.0 * sdnn * sdnn - sd1 * sd1).max(0.0).sqrt();
+
+    Some(HrvMetrics {
+        beats: beat_times.len(),
+        nn_intervals: normal.len(),
+        ectopic_removed: intervals.len() - normal.len(),
+        mean_nn,
+        sdnn,
+        rmssd,
+        pnn50,
+        triangular_index: triangular_index(&normal),
+        sd1,
+        sd2,
+        spectrum: lomb_scargle_bands(&normal),
+    })
+}
+
+// Drops intervals outside the plausible range or far from their neighbours' median
+fn filter_ectopic(intervals: &[Interval]) -> Vec<Interval> {
+    intervals
+        .iter()
+        .enumerate()
+        .filter(|(idx, interval)| {
+            if !(MIN_RR..=MAX_RR).contains(&interval.rr) {
+                return false;
+            }
+            let start = idx.saturating_sub(ECTOPIC_NEIGHBOURS);
+            let end = (idx + ECTOPIC_NEIGHBOURS + 1).min(intervals.len());
+            let mut neighbours: Vec<f64> = intervals[start..end]
+                .iter()
+                .enumerate()
+                .filter(|&(offset, _)| start + offset != *idx)
+                .map(|(_, neighbour)| neighbour.rr)
+                .collect();
+            if neighbours.is_empty() {
+                return true;
+            }
+            neighbours.sort_by(f64::total_cmp);
+            let median = neighbours[neighbours.len() / 2];
+            (interval.rr - median).abs() <= ECTOPIC_TOLERANCE * median
+        })
+        .map(|(_, interval)| *interval)
+        .collect()
+}
+
+// Number of NN intervals divided by the height of their histogram's tallest bin
+fn triangular_index(intervals: &[Interval]) -> f64 {
+    let mut bins = std::collections::HashMap::new();
+    for interval in intervals {
+        *bins
+            .entry((interval.rr / HISTOGRAM_BIN) as i64)
+            .or_insert(0usize) += 1;
+    }
+    let tallest = bins.values().copied().max().unwrap_or(0);
+    if tallest == 0 {
+        0.0
+    } else {
+        intervals.len() as f64 / tallest as f64
+    }
+}
+
+// The Lomb–Scargle periodogram handles the uneven spacing of beats without
+// resampling. It is scaled so the spectrum integrates to the NN variance.
+fn lomb_scargle_bands(intervals: &[Interval]) -> SpectralPower {
+    let n = intervals.len() as f64;
+    let mean = intervals.iter().map(|interval| interval.rr).sum::<f64>() / n;
+    let values: Vec<(f64, f64)> = intervals
+        .iter()
+        .map(|interval| (interval.time, (interval.rr - mean) * 1000.0))
+        .collect();
+    let variance = values.iter().map(|(_, y)| y * y).sum::<f64>() / (n - 1.0);
+
+    // Up to the Nyquist frequency of the average beat rate
+    let max_frequency = 0.5 / mean;
+    let frequencies: Vec<f64> = (1..)
+        .map(|step| step as f64 * FREQUENCY_STEP)
+        .take_while(|&f| f <= max_frequency)
+        .collect();
+
+    let power: Vec<f64> = frequencies
+        .iter()
+        .map(|&frequency| {
+            let omega = 2.0 * std::f64::consts::PI * frequency;
+            let (sin_sum, cos_sum) = values.iter().fold((0.0, 0.0), |(s, c), (t, _)| {
+                (s + (2.0 * omega * t).sin(), c + (2.0 * omega * t).cos())
+            });
+            let tau = sin_sum.atan2(cos_sum) / (2.0 * omega);
+
+            let (mut yc, mut ys, mut cc, mut ss) = (0.0, 0.0, 0.0, 0.0);
+            for (t, y) in &values {
+                let (sin, cos) = (omega * (t - tau)).sin_cos();
+                yc += y * cos;
+                ys += y * sin;
+                cc += cos * cos;
+                ss += sin * sin;
+            }
+            0.5 * (yc * yc / cc.max(f64::EPSILON) + ys * ys / ss.max(f64::EPSILON))
+        })
+        .collect();
+
+    let total: f64 = power.iter().sum::<f64>() * FREQUENCY_STEP;
+    let scale = if total > 0.0 { variance / total } else { 0.0 };
+    let band = |(low, high): (f64, f64)| {
+        frequencies
+            .iter()
+            .zip(&power)
+            .filter(|(&f, _)| f >= low && f < high)
+            .map(|(_, &p)| p * scale * FREQUENCY_STEP)
+            .sum()
+    };
+
+    SpectralPower {
+        vlf: band(VLF_BAND),
+        lf: band(LF_BAND),
+        hf: band(HF_BAND),
+    }
+}
@@ -0,0 +1,317 @@
+use crate::alarms::units_per_millivolt;
+use crate::{ChannelInfo, EcgFrame};
+use std::fmt;
+
+// The twelve standard leads, in the order of a 12-lead report. The names are
+// the leads' own Roman numerals and abbreviations.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lead {
+    I,
+    II,
+    III,
+    AVR,
+    AVL,
+    AVF,
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+}
+
+impl Lead {
+    pub const ALL: [Lead; 12] = [
+        Lead::I,
+        Lead::II,
+        Lead::III,
+        Lead::AVR,
+        Lead::AVL,
+        Lead::AVF,
+        Lead::V1,
+        Lead::V2,
+        Lead::V3,
+        Lead::V4,
+        Lead::V5,
+        Lead::V6,
+    ];
+
+    const CHEST: [Lead; 6] = [Lead::V1, Lead::V2, Lead::V3, Lead::V4, Lead::V5, Lead::V6];
+
+    // Recognizes EDF labels such as "ECG II", "Lead aVR", "V5" or "MLII"
+    pub fn from_label(label: &str) -> Option<Lead> {
+        let name = label_name(label);
+        let name = name.strip_prefix("LEAD").unwrap_or(&name);
+        match name {
+            "I" => Some(Lead::I),
+            // MIT-BIH's modified limb lead II
+            "II" | "MLII" => Some(Lead::II),
+            "III" => Some(Lead::III),
+            "AVR" => Some(Lead::AVR),
+            "AVL" => Some(Lead::AVL),
+            "AVF" => Some(Lead::AVF),
+            _ => {
+                let index: usize = name.strip_prefix('V')?.parse().ok()?;
+                Self::CHEST.get(index.checked_sub(1)?).copied()
+            }
+        }
+    }
+
+    // EDF+ standard texts label ECG signals "ECG <lead>"
+    pub fn edf_label(&self) -> String {
+        format!("ECG {}", self)
+    }
+}
+
+impl fmt::Display for Lead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Lead::I => "I",
+            Lead::II => "II",
+            Lead::III => "III",
+            Lead::AVR => "aVR",
+            Lead::AVL => "aVL",
+            Lead::AVF => "aVF",
+            Lead::V1 => "V1",
+            Lead::V2 => "V2",
+            Lead::V3 => "V3",
+            Lead::V4 => "V4",
+            Lead::V5 => "V5",
+            Lead::V6 => "V6",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Raw electrode potentials: right arm, left arm, left leg and the six chest
+// positions (C1-C6 in IEC naming)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Electrode {
+    RightArm,
+    LeftArm,
+    LeftLeg,
+    Chest(usize),
+}
+
+impl Electrode {
+    // Chest positions need the "ECG" signal type, since EEG electrodes C1-C6
+    // have the same names
+    fn from_label(label: &str) -> Option<Electrode> {
+        let name = label_name(label);
+        match name.as_str() {
+            "RA" => Some(Electrode::RightArm),
+            "LA" => Some(Electrode::LeftArm),
+            "LL" => Some(Electrode::LeftLeg),
+            _ => {
+                if !label.trim().to_uppercase().starts_with("ECG") {
+                    return None;
+                }
+                let index: usize = name.strip_prefix('C')?.parse().ok()?;
+                (1..=6)
+                    .contains(&index)
+                    .then_some(Electrode::Chest(index - 1))
+            }
+        }
+    }
+}
+
+// Upper-case label without the "ECG" signal type and separators
+fn label_name(label: &str) -> String {
+    let label = label.trim();
+    let label = label
+        .strip_prefix("ECG")
+        .or_else(|| label.strip_prefix("ecg"))
+        .unwrap_or(label);
+    label
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// Weighted sum of channels making up one lead
+type Combination = Vec<(usize, f32)>;
+
+// Factor taking values in unit `from` to unit `to`; only voltages convert
+fn unit_conversion(from: &str, to: &str) -> Option<f32> {
+    const VOLTAGE_UNITS: [&str; 5] = ["uV", "µV", "μV", "mV", "V"];
+    let (from, to) = (from.trim(), to.trim());
+    if from == to {
+        Some(1.0)
+    } else if VOLTAGE_UNITS.contains(&from) && VOLTAGE_UNITS.contains(&to) {
+        Some(units_per_millivolt(to) / units_per_millivolt(from))
+    } else {
+        None
+    }
+}
+
+fn combine(a: &Combination, a_weight: f32, b: &Combination, b_weight: f32) -> Combination {
+    let mut sum: Combination = Vec::new();
+    for (channel, weight) in a
+        .iter()
+        .map(|&(channel, weight)| (channel, weight * a_weight))
+        .chain(
+            b.iter()
+                .map(|&(channel, weight)| (channel, weight * b_weight)),
+        )
+    {
+        match sum.iter_mut().find(|(existing, _)| *existing == channel) {
+            Some((_, total)) => *total += weight,
+            None => sum.push((channel, weight)),
+        }
+    }
+    sum.retain(|&(_, weight)| weight != 0.0);
+    sum
+}
+
+// Standard leads the recorded channels do not carry, derived from the ones
+// they do. Any two limb leads give the third (Einthoven, I + III = II) and the
+// augmented leads (Goldberger); raw electrode potentials give every limb lead
+// and, against Wilson's central terminal, the chest leads.
+pub struct LeadDerivation {
+    derived: Vec<(Lead, Combination)>,
+    // Leads left out because their sources' units don't convert
+    skipped: Vec<Lead>,
+}
+
+impl LeadDerivation {
+    pub fn new(channels: &[ChannelInfo]) -> Self {
+        let mut leads: Vec<Option<Combination>> = vec![None; Lead::ALL.len()];
+        let mut electrodes: Vec<(Electrode, usize)> = Vec::new();
+        for (channel, info) in channels.iter().enumerate() {
+            if let Some(lead) = Lead::from_label(&info.label) {
+                let slot = &mut leads[lead as usize];
+                if slot.is_none() {
+                    *slot = Some(vec![(channel, 1.0)]);
+                }
+            } else if let Some(electrode) = Electrode::from_label(&info.label) {
+                electrodes.push((electrode, channel));
+            }
+        }
+        let recorded: Vec<bool> = leads.iter().map(Option::is_some).collect();
+
+        let electrode = |wanted: Electrode| -> Option<Combination> {
+            electrodes
+                .iter()
+                .find(|(electrode, _)| *electrode == wanted)
+                .map(|&(_, channel)| vec![(channel, 1.0)])
+        };
+        let limb_electrodes = (
+            electrode(Electrode::RightArm),
+            electrode(Electrode::LeftArm),
+            electrode(Electrode::LeftLeg),
+        );
+        if let (Some(ra), Some(la), Some(ll)) = &limb_electrodes {
+            let slots = [
+                (Lead::I, combine(la, 1.0, ra, -1.0)),
+                (Lead::II, combine(ll, 1.0, ra, -1.0)),
+            ];
+            for (lead, combination) in slots {
+                leads[lead as usize].get_or_insert(combination);
+            }
+
+            // Wilson's central terminal, the mean of the three limb electrodes
+            let wilson = combine(&combine(ra, 1.0, la, 1.0), 1.0 / 3.0, ll, 1.0 / 3.0);
+            for (index, lead) in Lead::CHEST.iter().enumerate() {
+                if let Some(chest) = electrode(Electrode::Chest(index)) {
+                    leads[*lead as usize].get_or_insert(combine(&chest, 1.0, &wilson, -1.0));
+                }
+            }
+        }
+
+        // Einthoven: II = I + III
+        let limb = |leads: &[Option<Combination>], lead: Lead| leads[lead as usize].clone();
+        match (
+            limb(&leads, Lead::I),
+            limb(&leads, Lead::II),
+            limb(&leads, Lead::III),
+        ) {
+            (Some(i), Some(ii), None) => {
+                leads[Lead::III as usize] = Some(combine(&ii, 1.0, &i, -1.0))
+            }
+            (Some(i), None, Some(iii)) => {
+                leads[Lead::II as usize] = Some(combine(&i, 1.0, &iii, 1.0))
+            }
+            (None, Some(ii), Some(iii)) => {
+                leads[Lead::I as usize] = Some(combine(&ii, 1.0, &iii, -1.0))
+            }
+            _ => {}
+        }
+
+        // Goldberger: aVR = -(I + II) / 2, aVL = I - II / 2, aVF = II - I / 2
+        if let (Some(i), Some(ii)) = (limb(&leads, Lead::I), limb(&leads, Lead::II)) {
+            let augmented = [
+                (Lead::AVR, combine(&i, -0.5, &ii, -0.5)),
+                (Lead::AVL, combine(&i, 1.0, &ii, -0.5)),
+                (Lead::AVF, combine(&ii, 1.0, &i, -0.5)),
+            ];
+            for (lead, combination) in augmented {
+                leads[lead as usize].get_or_insert(combination);
+            }
+        }
+
+        let mut derived = Vec::new();
+        let mut skipped = Vec::new();
+        for ((&lead, combination), recorded) in Lead::ALL.iter().zip(leads).zip(recorded) {
+            let Some(combination) = combination.filter(|_| !recorded) else {
+                continue;
+            };
+            match Self::in_common_unit(&combination, channels) {
+                Some(converted) => derived.push((lead, converted)),
+                None => skipped.push(lead),
+            }
+        }
+        Self { derived, skipped }
+    }
+
+    // The combination with its weights converted to the unit of its first
+    // source, or None when the sources' units can't be converted
+    fn in_common_unit(combination: &Combination, channels: &[ChannelInfo]) -> Option<Combination> {
+        let unit = |channel: usize| channels.get(channel).map_or("", |info| info.unit.as_str());
+        let target = unit(combination.first()?.0);
+        combination
+            .iter()
+            .map(|&(channel, weight)| {
+                unit_conversion(unit(channel), target).map(|factor| (channel, weight * factor))
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.derived.is_empty()
+    }
+
+    pub fn skipped(&self) -> &[Lead] {
+        &self.skipped
+    }
+
+    // Channels to add after the recorded ones, in the unit of their first source
+    pub fn channels(&self, recorded: &[ChannelInfo]) -> Vec<ChannelInfo> {
+        self.derived
+            .iter()
+            .map(|(lead, combination)| ChannelInfo {
+                label: lead.edf_label(),
+                unit: combination
+                    .first()
+                    .and_then(|&(channel, _)| recorded.get(channel))
+                    .map_or(String::new(), |source| source.unit.clone()),
+            })
+            .collect()
+    }
+
+    // Appends the derived leads' values to a frame of recorded channels
+    pub fn extend(&self, frame: &mut EcgFrame) {
+        let values: Vec<f32> = self
+            .derived
+            .iter()
+            .map(|(_, combination)| {
+                combination
+                    .iter()
+                    .map(|&(channel, weight)| frame.value(channel) * weight)
+                    .sum()
+            })
+            .collect();
+        frame.values.extend(values);
+    }
+}
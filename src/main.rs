@@ -1,41 +1,200 @@
 use eframe::egui;
 use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
+mod alarms;
+mod ctg;
+mod deidentify;
 mod ecg_display;
+mod ectopy;
 mod edf_parser;
+mod edf_writer;
+mod evaluation;
+mod fetal_ecg;
+mod filters;
+mod heart_rate;
+mod hrv;
+mod leads;
+mod qrs_detector;
 mod qrs_parser;
+mod sample_source;
 
-use ecg_display::EcgDisplay;
-use edf_parser::EdfReader;
-use qrs_parser::QrsReader;
+use alarms::{AlarmEngine, AlarmLimits};
+use ctg::{CtgMonitor, CtgStatus};
+use ecg_display::{EcgDisplay, ScreenLayout};
+use ectopy::{BeatLabeler, EctopyCounter, EctopySummary};
+use edf_parser::{EdfAnnotation, PatientInfo, RecordingInfo, Signal};
+use filters::{FilterChain, FilterConfig, FilterMode, FilterSettings};
+use heart_rate::{HeartRateMeter, HrAveraging};
+use hrv::HrvMetrics;
+use leads::{Lead, LeadDerivation};
+use qrs_detector::QrsDetector;
+use qrs_parser::BeatType;
+use sample_source::{EdfStream, SampleSource};
 
 const WINDOW_WIDTH: f32 = 1024.0;
 const WINDOW_HEIGHT: f32 = 768.0;
-const SAMPLE_RATE: f32 = 360.0; // Common ECG sample rate
+const SYNTHETIC_SAMPLE_RATE: f32 = 360.0; // Common ECG sample rate
 const DISPLAY_SECONDS: f32 = 10.0; // Show 10 seconds of data
-const MAX_SAMPLES: usize = (SAMPLE_RATE * DISPLAY_SECONDS) as usize;
+const STREAM_TICK: Duration = Duration::from_millis(10);
+const ALARM_LIMITS_FILE: &str = "alarm_limits.json";
 
+// One sampling instant of every channel in the recording
 #[derive(Clone)]
-pub struct EcgSample {
+pub struct EcgFrame {
     pub timestamp: f64,
-    pub lead1: f32,
-    pub lead2: f32,
-    pub lead_v1: f32,
-    pub is_qrs: bool,
+    // One value per channel, in the order of `RecordingDescriptor::channels`
+    pub values: Vec<f32>,
+    // Set on the R-peak frame of each beat
+    pub beat: Option<BeatType>,
+}
+
+impl EcgFrame {
+    // Value of a channel, or zero where a short record left it without data
+    pub fn value(&self, channel: usize) -> f32 {
+        self.values.get(channel).copied().unwrap_or(0.0)
+    }
+}
+
+// Label and physical unit of a recorded channel, as given in the EDF header
+#[derive(Clone, Debug)]
+pub struct ChannelInfo {
+    pub label: String,
+    pub unit: String,
+}
+
+// Seconds of beats kept for heart rate variability, the standard short-term window
+const HRV_WINDOW_SECONDS: f64 = 300.0;
+// The full analysis is too slow to redo on every beat
+const HRV_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+// The CTG analysis covers up to an hour of trend, so it is refreshed every
+// second rather than every tick
+const CTG_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Measurements derived from the sample stream, shared with the display
+#[derive(Clone, Default)]
+pub struct Vitals {
+    pub heart_rate: Option<f32>,
+    pub spo2: Option<f32>,
+    pub hrv: Option<HrvMetrics>,
+    pub ectopy: EctopySummary,
+    // Maternal and fetal readings, once fetal beats have been extracted
+    pub ctg: Option<CtgStatus>,
+    // Set when the sample source failed and stopped
+    pub source_error: Option<String>,
+}
+
+// Describes where the sample stream came from and how to interpret its timing
+#[derive(Clone, Debug)]
+pub struct RecordingDescriptor {
+    pub source: String,
+    pub sample_rate: f32,
+    pub channels: Vec<ChannelInfo>,
+    pub patient: PatientInfo,
+    pub recording_info: RecordingInfo,
+}
+
+impl RecordingDescriptor {
+    // Number of samples that fit in the on-screen sweep
+    pub fn display_capacity(&self) -> usize {
+        (self.sample_rate * DISPLAY_SECONDS) as usize
+    }
+
+    // Channel that beats are detected, and the alarms watch the signal, on: a
+    // surface ECG lead if there is one. Fetal scalp ("Direct") electrodes are
+    // never used, and abdominal leads only when nothing better is recorded,
+    // since they mostly pick up the maternal ECG.
+    pub fn detection_channel(&self) -> usize {
+        let label = |channel: &ChannelInfo| channel.label.to_lowercase();
+        let is_fetal = |channel: &ChannelInfo| {
+            let label = label(channel);
+            label.contains("direct") || label.contains("abdom")
+        };
+        self.channels
+            .iter()
+            .position(|channel| {
+                !is_fetal(channel)
+                    && (Lead::from_label(&channel.label).is_some()
+                        || label(channel).contains("ecg"))
+            })
+            .or_else(|| {
+                self.channels
+                    .iter()
+                    .position(|channel| !label(channel).contains("direct"))
+            })
+            .unwrap_or(0)
+    }
+
+    pub fn detection_unit(&self) -> &str {
+        self.channels
+            .get(self.detection_channel())
+            .map_or("", |channel| channel.unit.as_str())
+    }
+
+    // Adds the standard leads that can be derived from the recorded channels
+    // after them; frames are extended to match with the returned derivation
+    pub fn derive_leads(&mut self) -> LeadDerivation {
+        let derivation = LeadDerivation::new(&self.channels);
+        if !derivation.is_empty() {
+            let derived = derivation.channels(&self.channels);
+            println!(
+                "  derived {}",
+                derived
+                    .iter()
+                    .map(|channel| channel.label.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            self.channels.extend(derived);
+        }
+        if !derivation.skipped().is_empty() {
+            println!(
+                "  not deriving {}: their sources' units differ",
+                derivation
+                    .skipped()
+                    .iter()
+                    .map(Lead::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        derivation
+    }
+
+    // The three leads of the built-in synthetic ECG
+    fn synthetic() -> Self {
+        RecordingDescriptor {
+            source: "synthetic ECG".to_string(),
+            sample_rate: SYNTHETIC_SAMPLE_RATE,
+            channels: ["ECG I", "ECG II", "ECG V1"]
+                .iter()
+                .map(|label| ChannelInfo {
+                    label: label.to_string(),
+                    unit: "mV".to_string(),
+                })
+                .collect(),
+            patient: PatientInfo::default(),
+            recording_info: RecordingInfo::default(),
+        }
+    }
 }
 
 pub struct EcgMonitor {
-    samples: Arc<Mutex<VecDeque<EcgSample>>>,
-    heart_rate: Arc<Mutex<f32>>,
+    samples: Arc<Mutex<VecDeque<EcgFrame>>>,
+    vitals: Arc<Mutex<Vitals>>,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    hr_averaging: Arc<Mutex<HrAveraging>>,
     is_running: Arc<Mutex<bool>>,
     display_speed: Arc<Mutex<f32>>,
-    edf_data: Vec<EcgSample>,
-    current_index: usize,
-    last_update: Instant,
+    // Filters of each display mode, applied by the data thread
+    filters: Arc<Mutex<FilterConfig>>,
+    show_filters_dialog: bool,
     display: EcgDisplay,
+    recording: RecordingDescriptor,
+    // Limits being edited while the settings dialog is open
+    limits_dialog: Option<AlarmLimits>,
 }
 
 impl Default for EcgMonitor {
@@ -46,75 +205,101 @@ impl Default for EcgMonitor {
 
 impl EcgMonitor {
     fn new() -> Self {
-        let samples = Arc::new(Mutex::new(VecDeque::with_capacity(MAX_SAMPLES)));
-        let heart_rate = Arc::new(Mutex::new(75.0));
-        let is_running = Arc::new(Mutex::new(true));
-        let display_speed = Arc::new(Mutex::new(1.0));
+        // Open the ECG source; EDF data is streamed from disk by the data thread
+        let (recording, source) = Self::open_ecg_source();
+        println!(
+            "Streaming {} at {} Hz",
+            recording.source, recording.sample_rate
+        );
 
-        // Load ECG data
-        let edf_data = Self::load_ecg_data();
+        let samples = VecDeque::with_capacity(recording.display_capacity());
+        // Limits saved by the settings dialog, if there are any
+        let limits = if std::path::Path::new(ALARM_LIMITS_FILE).exists() {
+            AlarmLimits::load(ALARM_LIMITS_FILE).unwrap_or_else(|err| {
+                println!(
+                    "Could not load {} ({}), using defaults",
+                    ALARM_LIMITS_FILE, err
+                );
+                AlarmLimits::default()
+            })
+        } else {
+            AlarmLimits::default()
+        };
+        let alarms = AlarmEngine::new(limits, recording.detection_unit());
 
         let monitor = Self {
-            samples: samples.clone(),
-            heart_rate: heart_rate.clone(),
-            is_running: is_running.clone(),
-            display_speed: display_speed.clone(),
-            edf_data,
-            current_index: 0,
-            last_update: Instant::now(),
-            display: EcgDisplay::new(),
+            samples: Arc::new(Mutex::new(samples)),
+            vitals: Arc::new(Mutex::new(Vitals::default())),
+            alarms: Arc::new(Mutex::new(alarms)),
+            hr_averaging: Arc::new(Mutex::new(HrAveraging::default())),
+            is_running: Arc::new(Mutex::new(true)),
+            display_speed: Arc::new(Mutex::new(1.0)),
+            filters: Arc::new(Mutex::new(FilterConfig::default())),
+            show_filters_dialog: false,
+            display: EcgDisplay::new(&recording),
+            recording,
+            limits_dialog: None,
+        };
+
+        // Fetal beats are extracted from the whole file in the background,
+        // for recordings that have abdominal leads
+        let has_abdominal_leads = monitor
+            .recording
+            .channels
+            .iter()
+            .any(|channel| fetal_ecg::is_abdominal(&channel.label));
+        let fetal_beats = match &source {
+            SampleSource::Edf(_) if has_abdominal_leads => {
+                Some(Self::start_fetal_extraction(&monitor.recording))
+            }
+            _ => None,
         };
 
         // Start data streaming thread
-        Self::start_data_thread(samples, heart_rate, is_running, display_speed);
+        monitor.start_data_thread(source, fetal_beats);
 
         monitor
     }
 
-    fn load_ecg_data() -> Vec<EcgSample> {
-        let mut samples = Vec::new();
-
+    fn open_ecg_source() -> (RecordingDescriptor, SampleSource) {
         // Try to load EDF file
-        if let Ok(mut edf_reader) = EdfReader::new("r01.edf") {
-            println!("Successfully loaded EDF file");
-
-            // Load QRS annotations
-            let qrs_annotations =
-                QrsReader::load_annotations("r01.edf.qrs").unwrap_or_else(|_| Vec::new());
-
-            let signal_data = edf_reader.read_signals().unwrap_or_else(|_| Vec::new());
+        match EdfStream::open("r01.edf", "r01.edf.qrs") {
+            Ok((recording, stream)) => {
+                println!("Successfully loaded EDF file");
+                (recording, SampleSource::Edf(Box::new(stream)))
+            }
+            Err(err) => {
+                // Fallback: Generate synthetic ECG data
+                println!(
+                    "Could not load EDF file ({}), generating synthetic ECG data",
+                    err
+                );
+                let mut recording = RecordingDescriptor::synthetic();
+                let mut samples = Self::generate_synthetic_ecg(recording.sample_rate);
+                let derivation = recording.derive_leads();
+                samples
+                    .iter_mut()
+                    .for_each(|sample| derivation.extend(sample));
 
-            for (i, data_point) in signal_data.iter().enumerate() {
-                let timestamp = i as f64 / SAMPLE_RATE as f64;
-                let is_qrs = qrs_annotations
-                    .iter()
-                    .any(|&qrs_time| (qrs_time - timestamp).abs() < 0.01);
-
-                samples.push(EcgSample {
-                    timestamp,
-                    lead1: data_point.get(0).copied().unwrap_or(0.0),
-                    lead2: data_point.get(1).copied().unwrap_or(0.0),
-                    lead_v1: data_point.get(2).copied().unwrap_or(0.0),
-                    is_qrs,
-                });
+                (
+                    recording,
+                    SampleSource::Memory {
+                        samples,
+                        position: 0,
+                    },
+                )
             }
-        } else {
-            // Fallback: Generate synthetic ECG data
-            println!("Could not load EDF file, generating synthetic ECG data");
-            samples = Self::generate_synthetic_ecg();
         }
-
-        samples
     }
 
-    fn generate_synthetic_ecg() -> Vec<EcgSample> {
+    fn generate_synthetic_ecg(sample_rate: f32) -> Vec<EcgFrame> {
         let mut samples = Vec::new();
         let duration = 60.0; // 60 seconds of data
         let heart_rate = 75.0;
         let rr_interval = 60.0 / heart_rate;
 
-        for i in 0..(duration * SAMPLE_RATE) as usize {
-            let t = i as f64 / SAMPLE_RATE as f64;
+        for i in 0..(duration * sample_rate) as usize {
+            let t = i as f64 / sample_rate as f64;
             let heart_cycle = (t % rr_interval) / rr_interval;
 
             // Generate realistic ECG waveform
@@ -125,20 +310,289 @@ impl EcgMonitor {
             // Add some noise
             let noise = (rand::random::<f32>() - 0.5) * 0.05;
 
-            let is_qrs = heart_cycle > 0.15 && heart_cycle < 0.25;
+            // The R wave peaks at 0.19 of the cycle
+            let cycle_step = 1.0 / (rr_interval * sample_rate as f64);
+            let is_r_peak = heart_cycle >= 0.19 && heart_cycle - cycle_step < 0.19;
 
-            samples.push(EcgSample {
+            samples.push(EcgFrame {
                 timestamp: t,
-                lead1: lead1 + noise,
-                lead2: lead2 + noise,
-                lead_v1: lead_v1 + noise,
-                is_qrs,
+                values: vec![lead1 + noise, lead2 + noise, lead_v1 + noise],
+                beat: is_r_peak.then_some(BeatType::Normal),
             });
         }
 
         samples
     }
 
+    // Writes samples to an EDF+ file, one signal per channel, with each beat
+    // annotated at its R peak by its class mnemonic ("N", "V", ...)
+    fn write_samples_edf(
+        filename: &str,
+        samples: &[EcgFrame],
+        recording: &RecordingDescriptor,
+    ) -> Result<u32, Box<dyn std::error::Error>> {
+        let fs = recording.sample_rate as f64;
+        let signals: Vec<Signal> = recording
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(channel, info)| Signal {
+                label: info.label.clone(),
+                fs,
+                unit: info.unit.clone(),
+                samples: samples.iter().map(|sample| sample.value(channel)).collect(),
+            })
+            .collect();
+
+        let annotations: Vec<EdfAnnotation> = samples
+            .iter()
+            .enumerate()
+            .filter_map(|(i, sample)| {
+                sample.beat.map(|beat| EdfAnnotation {
+                    onset: i as f64 / fs,
+                    duration: None,
+                    text: beat.mnemonic().to_string(),
+                })
+            })
+            .collect();
+
+        edf_writer::write_edf(filename, &signals, &annotations)
+    }
+
+    // Saves the trace currently on screen next to the executable's working directory
+    fn save_recording(&self) {
+        let samples: Vec<EcgFrame> = self.samples.lock().unwrap().iter().cloned().collect();
+        let filename = format!("ecg_{}.edf", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+
+        match Self::write_samples_edf(&filename, &samples, &self.recording) {
+            Ok(records) => println!("Saved {} ({} records)", filename, records),
+            Err(err) => println!("Could not save {}: {}", filename, err),
+        }
+    }
+
+    // Adds a frame to the display queue, dropping the oldest once it is full
+    fn push_display(queue: &mut VecDeque<EcgFrame>, frame: EcgFrame, capacity: usize) {
+        if queue.len() >= capacity {
+            queue.pop_front();
+        }
+        queue.push_back(frame);
+    }
+
+    // Simulated saturation; there is no oximeter input yet
+    fn simulated_spo2() -> f32 {
+        let current_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        // Simulate realistic SpO2 variation with breathing correlation
+        let base_spo2 = 97.5;
+        let breathing_effect = (current_time * 0.3).sin() * 0.5; // Small breathing correlation
+        let measurement_noise = ((current_time * 13.2).sin() * (current_time * 7.8).cos()) * 0.8;
+        (base_spo2 + breathing_effect + measurement_noise)
+            .clamp(94.0, 100.0)
+            .round() as f32
+    }
+
+    // Settings window for the alarm limits; Apply takes effect at once and
+    // saves them for the next start
+    fn show_limits_dialog(&mut self, ctx: &egui::Context) {
+        let Some(limits) = self.limits_dialog.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut close = false;
+        egui::Window::new("Alarm Limits")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("alarm_limits")
+                    .num_columns(3)
+                    .spacing([20.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("");
+                        ui.label("LOW");
+                        ui.label("HIGH");
+                        ui.end_row();
+
+                        let range_row = |ui: &mut egui::Ui,
+                                         label: &str,
+                                         range: &mut alarms::LimitRange,
+                                         bounds: std::ops::RangeInclusive<f32>,
+                                         suffix: &str| {
+                            ui.label(label);
+                            ui.add(
+                                egui::DragValue::new(&mut range.low)
+                                    .clamp_range(*bounds.start()..=range.high)
+                                    .suffix(suffix),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut range.high)
+                                    .clamp_range(range.low..=*bounds.end())
+                                    .suffix(suffix),
+                            );
+                            ui.end_row();
+                        };
+                        range_row(
+                            ui,
+                            "HR",
+                            &mut limits.heart_rate,
+                            AlarmLimits::HEART_RATE_BOUNDS,
+                            " bpm",
+                        );
+                        range_row(ui, "SpO2", &mut limits.spo2, AlarmLimits::SPO2_BOUNDS, " %");
+
+                        ui.label("Asystole after");
+                        ui.add(
+                            egui::DragValue::new(&mut limits.asystole_seconds)
+                                .clamp_range(AlarmLimits::ASYSTOLE_BOUNDS)
+                                .speed(0.1)
+                                .suffix(" s"),
+                        );
+                        ui.end_row();
+
+                        ui.label("V-tach run");
+                        ui.add(
+                            egui::DragValue::new(&mut limits.vt_beats)
+                                .clamp_range(AlarmLimits::VT_BEATS_BOUNDS)
+                                .suffix(" beats"),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut limits.vt_rate_bpm)
+                                .clamp_range(AlarmLimits::VT_RATE_BOUNDS)
+                                .suffix(" bpm"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Irregular R-R above");
+                        ui.add(
+                            egui::DragValue::new(&mut limits.af_irregularity)
+                                .clamp_range(AlarmLimits::AF_IRREGULARITY_BOUNDS)
+                                .speed(0.005)
+                                .fixed_decimals(3),
+                        );
+                        ui.end_row();
+
+                        ui.label("Signal loss after");
+                        ui.add(
+                            egui::DragValue::new(&mut limits.flatline_seconds)
+                                .clamp_range(AlarmLimits::FLATLINE_BOUNDS)
+                                .speed(0.1)
+                                .suffix(" s"),
+                        );
+                        ui.end_row();
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    if ui.button("Defaults").clicked() {
+                        *limits = AlarmLimits::default();
+                    }
+                    close = ui.button("Close").clicked();
+                });
+            });
+
+        if apply {
+            self.alarms.lock().unwrap().set_limits(*limits);
+            match limits.save(ALARM_LIMITS_FILE) {
+                Ok(()) => println!("Saved alarm limits to {}", ALARM_LIMITS_FILE),
+                Err(err) => println!("Could not save {}: {}", ALARM_LIMITS_FILE, err),
+            }
+        }
+        if !open || close {
+            self.limits_dialog = None;
+        }
+    }
+
+    // Settings window for the filters of the selected display mode; changes
+    // reach the traces at once
+    fn show_filters_dialog(&mut self, ctx: &egui::Context) {
+        if !self.show_filters_dialog {
+            return;
+        }
+
+        let mut config = *self.filters.lock().unwrap();
+        let mut open = true;
+        let mut close = false;
+        egui::Window::new(format!("{} Filters", config.mode))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let defaults = config.mode.default_settings();
+                let settings = config.active_mut();
+                let cutoff =
+                    |hz: Option<f32>| hz.map_or("Off".to_string(), |hz| format!("{} Hz", hz));
+                egui::Grid::new("filters")
+                    .num_columns(2)
+                    .spacing([20.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("High pass");
+                        egui::ComboBox::from_id_source("high_pass")
+                            .selected_text(cutoff(settings.high_pass))
+                            .show_ui(ui, |ui| {
+                                for option in FilterSettings::HIGH_PASS_OPTIONS {
+                                    ui.selectable_value(
+                                        &mut settings.high_pass,
+                                        option,
+                                        cutoff(option),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Mains");
+                        egui::ComboBox::from_id_source("mains")
+                            .selected_text(settings.mains.to_string())
+                            .show_ui(ui, |ui| {
+                                for option in FilterSettings::MAINS_OPTIONS {
+                                    ui.selectable_value(
+                                        &mut settings.mains,
+                                        option,
+                                        option.to_string(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Low pass");
+                        egui::ComboBox::from_id_source("low_pass")
+                            .selected_text(cutoff(settings.low_pass))
+                            .show_ui(ui, |ui| {
+                                for option in FilterSettings::LOW_PASS_OPTIONS {
+                                    ui.selectable_value(
+                                        &mut settings.low_pass,
+                                        option,
+                                        cutoff(option),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Median baseline");
+                        ui.checkbox(&mut settings.median_baseline, "");
+                        ui.end_row();
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Defaults").clicked() {
+                        *settings = defaults;
+                    }
+                    close = ui.button("Close").clicked();
+                });
+            });
+
+        *self.filters.lock().unwrap() = config;
+        if !open || close {
+            self.show_filters_dialog = false;
+        }
+    }
+
     fn ecg_waveform(t: f64, amplitude: f32) -> f32 {
         // Simplified ECG waveform generation
         let t = t as f32;
@@ -170,47 +624,225 @@ impl EcgMonitor {
         }
     }
 
+    // Maternal and fetal beat times of the recording's abdominal leads, sent
+    // once the extraction has finished; nothing is sent if it fails
+    fn start_fetal_extraction(
+        recording: &RecordingDescriptor,
+    ) -> mpsc::Receiver<(Vec<f64>, Vec<f64>)> {
+        let (sender, receiver) = mpsc::channel();
+        let edf_path = std::path::PathBuf::from(&recording.source);
+
+        thread::spawn(move || match fetal_ecg::extract_file(&edf_path) {
+            Ok(extraction) => {
+                println!(
+                    "Extracted {} fetal beats from {}",
+                    extraction.fetal_beats.len(),
+                    extraction.residuals[extraction.fetal_channel].label
+                );
+                sender
+                    .send((extraction.maternal_times(), extraction.fetal_times()))
+                    .ok();
+            }
+            Err(err) => println!("No fetal ECG: {}", err),
+        });
+
+        receiver
+    }
+
     fn start_data_thread(
-        samples: Arc<Mutex<VecDeque<EcgSample>>>,
-        _heart_rate: Arc<Mutex<f32>>,
-        is_running: Arc<Mutex<bool>>,
-        display_speed: Arc<Mutex<f32>>,
+        &self,
+        mut source: SampleSource,
+        fetal_beats: Option<mpsc::Receiver<(Vec<f64>, Vec<f64>)>>,
     ) {
+        let recording = self.recording.clone();
+        let samples = self.samples.clone();
+        let vitals = self.vitals.clone();
+        let alarms = self.alarms.clone();
+        let hr_averaging = self.hr_averaging.clone();
+        let is_running = self.is_running.clone();
+        let display_speed = self.display_speed.clone();
+        let filters = self.filters.clone();
+
         thread::spawn(move || {
-            let edf_data = Self::load_ecg_data();
-            let mut current_index = 0;
-            let mut _last_qrs_time = 0.0;
-            let _qrs_intervals: VecDeque<f64> = VecDeque::new();
+            let max_samples = recording.display_capacity();
+            let mut meter = HeartRateMeter::new(*hr_averaging.lock().unwrap());
+            let mut hrv_beats: VecDeque<f64> = VecDeque::new();
+            let mut hrv_changed = false;
+            let mut last_hrv_update = Instant::now();
+            let mut ectopy = EctopyCounter::new();
+            let mut ctg: Option<CtgMonitor> = None;
+            let mut last_ctg_update: Option<Instant> = None;
+
+            // Beats are detected on one lead; the source's own markers only
+            // supply the type of each detected beat
+            let detection_channel = recording.detection_channel();
+            if let Some(channel) = recording.channels.get(detection_channel) {
+                println!("Detecting beats on {}", channel.label);
+            }
+            let mut detector = QrsDetector::new(recording.sample_rate);
+            let mut labeler = BeatLabeler::new(
+                (evaluation::DEFAULT_TOLERANCE * recording.sample_rate as f64) as u64,
+            );
+            let mut samples_streamed: u64 = 0;
+            // Recording time of the newest sample, which the display lags
+            // while the filters hold frames back
+            let mut latest_time: Option<f64> = None;
+            let mut filter_chain: Option<FilterChain> = None;
+
+            // Fractional samples owed to the display, carried between ticks
+            let mut pending_samples = 0.0;
+            let mut last_tick = Instant::now();
 
             loop {
                 if !*is_running.lock().unwrap() {
                     thread::sleep(Duration::from_millis(100));
+                    last_tick = Instant::now();
                     continue;
                 }
 
+                if let Some((maternal, fetal)) = fetal_beats
+                    .as_ref()
+                    .and_then(|receiver| receiver.try_recv().ok())
+                {
+                    ctg = Some(CtgMonitor::new(maternal, fetal));
+                    last_ctg_update = None;
+                }
+
                 let speed = *display_speed.lock().unwrap();
-                let sleep_duration = Duration::from_millis((1000.0 / (SAMPLE_RATE * speed)) as u64);
+                let now = Instant::now();
+                pending_samples +=
+                    now.duration_since(last_tick).as_secs_f32() * recording.sample_rate * speed;
+                last_tick = now;
+
+                let mut samples_lock = samples.lock().unwrap();
 
-                if !edf_data.is_empty() {
-                    let sample = edf_data[current_index].clone();
+                // Start the filters afresh when the settings change, showing
+                // the frames the old chain still held back
+                let settings = filters.lock().unwrap().active();
+                let channels = recording.channels.len();
+                let chain = match filter_chain {
+                    Some(ref mut chain) if *chain.settings() == settings => chain,
+                    _ => {
+                        if let Some(old) = filter_chain.as_mut() {
+                            for frame in old.flush() {
+                                Self::push_display(&mut samples_lock, frame, max_samples);
+                            }
+                        }
+                        filter_chain.insert(FilterChain::new(
+                            settings,
+                            recording.sample_rate,
+                            channels,
+                        ))
+                    }
+                };
+
+                let mut alarms = alarms.lock().unwrap();
+                while pending_samples >= 1.0 {
+                    let Some(mut sample) = source.next() else {
+                        pending_samples = 0.0;
+                        if let Some(err) = source.error() {
+                            vitals.lock().unwrap().source_error = Some(err.to_string());
+                        }
+                        break;
+                    };
+                    pending_samples -= 1.0;
+
+                    // Detection and alarms see the raw signal; the filters are
+                    // for display only
+                    if let Some(beat_type) = sample.beat.take() {
+                        labeler.add_label(samples_streamed, beat_type);
+                    }
+                    if let Some(r_peak) = detector.process(sample.value(detection_channel)) {
+                        labeler.add_detection(r_peak);
+                    }
+                    alarms.add_sample(sample.timestamp, sample.value(detection_channel));
+                    let sample_time = sample.timestamp;
+                    latest_time = Some(sample_time);
+                    samples_streamed += 1;
+
+                    // Nothing comes out while the median baseline fills
+                    if let Some(filtered) = chain.process(sample) {
+                        Self::push_display(&mut samples_lock, filtered, max_samples);
+                    }
+
+                    // R peaks are reported after the fact, so mark the sample
+                    // already queued, or still held back by the filters. A
+                    // sample that has scrolled off only loses its marker; the
+                    // beat's time is then counted back from the newest sample.
+                    while let Some((r_peak, beat_type)) = labeler.next_beat(samples_streamed - 1) {
+                        let age = (samples_streamed - 1 - r_peak) as usize;
+                        let held = chain.pending_mut();
+                        let frame = match age.checked_sub(held.len()) {
+                            None => {
+                                let idx = held.len() - 1 - age;
+                                Some(&mut held[idx])
+                            }
+                            Some(shown_age) => samples_lock
+                                .len()
+                                .checked_sub(shown_age + 1)
+                                .map(|idx| &mut samples_lock[idx]),
+                        };
+                        let beat_time = match frame {
+                            Some(frame) => {
+                                frame.beat = Some(beat_type);
+                                frame.timestamp
+                            }
+                            None => sample_time - age as f64 / recording.sample_rate as f64,
+                        };
+                        meter.add_beat(beat_time);
+                        ectopy.add_beat(beat_time, beat_type);
+                        alarms.add_beat(beat_time, beat_type);
 
-                    // Heart rate calculation is now handled in the display module
-                    if sample.is_qrs {
-                        _last_qrs_time = sample.timestamp;
+                        // Start over when playback loops back to the beginning
+                        if hrv_beats.back().is_some_and(|&last| beat_time <= last) {
+                            hrv_beats.clear();
+                        }
+                        hrv_beats.push_back(beat_time);
+                        while hrv_beats
+                            .front()
+                            .is_some_and(|&first| beat_time - first > HRV_WINDOW_SECONDS)
+                        {
+                            hrv_beats.pop_front();
+                        }
+                        hrv_changed = true;
                     }
+                }
 
-                    // Add sample to display queue
-                    let mut samples_lock = samples.lock().unwrap();
-                    if samples_lock.len() >= MAX_SAMPLES {
-                        samples_lock.pop_front();
+                // Rate at the newest sample's recording time, not the wall clock
+                meter.set_averaging(*hr_averaging.lock().unwrap());
+                if let Some(latest) = latest_time {
+                    let mut vitals = vitals.lock().unwrap();
+                    vitals.heart_rate = meter.rate(latest);
+                    vitals.ectopy = ectopy.summary(latest);
+                    vitals.spo2 = Some(Self::simulated_spo2());
+                    if let Some(ctg) = ctg.as_mut() {
+                        ctg.advance(latest);
                     }
-                    samples_lock.push_back(sample);
-                    drop(samples_lock);
+                    alarms.set_heart_rate(vitals.heart_rate);
+                    alarms.set_spo2(vitals.spo2);
+                    alarms.evaluate();
+                }
+                drop(alarms);
+                drop(samples_lock);
 
-                    current_index = (current_index + 1) % edf_data.len();
+                // Analyze a copy of the beats without holding any lock
+                if hrv_changed && last_hrv_update.elapsed() >= HRV_UPDATE_INTERVAL {
+                    let beats: Vec<f64> = hrv_beats.iter().copied().collect();
+                    let metrics = hrv::analyze(&beats);
+                    vitals.lock().unwrap().hrv = metrics;
+                    hrv_changed = false;
+                    last_hrv_update = Instant::now();
                 }
 
-                thread::sleep(sleep_duration);
+                if let Some(ctg) = ctg.as_ref().filter(|_| {
+                    last_ctg_update.is_none_or(|last| last.elapsed() >= CTG_UPDATE_INTERVAL)
+                }) {
+                    let status = ctg.status();
+                    vitals.lock().unwrap().ctg = Some(status);
+                    last_ctg_update = Some(Instant::now());
+                }
+
+                thread::sleep(STREAM_TICK);
             }
         });
     }
@@ -230,7 +862,7 @@ impl eframe::App for EcgMonitor {
                     ui.add_space(20.0);
 
                     // Play/Pause button
-                    let mut is_running = self.is_running.lock().unwrap().clone();
+                    let mut is_running = *self.is_running.lock().unwrap();
                     let play_button = ui.add(
                         egui::Button::new(
                             egui::RichText::new(if is_running { "⏸ PAUSE" } else { "▶ PLAY" })
@@ -259,7 +891,7 @@ impl eframe::App for EcgMonitor {
                             .color(egui::Color32::WHITE),
                     );
 
-                    let mut speed = self.display_speed.lock().unwrap().clone();
+                    let mut speed = *self.display_speed.lock().unwrap();
                     if ui
                         .add(
                             egui::Slider::new(&mut speed, 0.1..=5.0)
@@ -275,6 +907,134 @@ impl eframe::App for EcgMonitor {
 
                     ui.add_space(30.0);
 
+                    // Heart rate averaging
+                    ui.label(
+                        egui::RichText::new("HR AVG:")
+                            .size(14.0)
+                            .color(egui::Color32::WHITE),
+                    );
+
+                    let mut averaging = *self.hr_averaging.lock().unwrap();
+                    egui::ComboBox::from_id_source("hr_averaging")
+                        .selected_text(averaging.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in HrAveraging::OPTIONS {
+                                ui.selectable_value(&mut averaging, option, option.to_string());
+                            }
+                        });
+                    *self.hr_averaging.lock().unwrap() = averaging;
+
+                    ui.add_space(30.0);
+
+                    // Traces on screen, chosen from the recording's channels
+                    ui.menu_button(
+                        egui::RichText::new("LEADS")
+                            .size(16.0)
+                            .color(egui::Color32::WHITE),
+                        |ui| self.display.channel_menu(ui),
+                    );
+
+                    ui.add_space(30.0);
+
+                    // Save the visible trace as EDF+
+                    let save_button = ui.add(
+                        egui::Button::new(
+                            egui::RichText::new("💾 SAVE")
+                                .size(16.0)
+                                .color(egui::Color32::WHITE),
+                        )
+                        .fill(egui::Color32::from_rgb(0, 70, 140))
+                        .min_size(egui::Vec2::new(100.0, 30.0)),
+                    );
+                    if save_button.clicked() {
+                        self.save_recording();
+                    }
+
+                    // Screen layout: monitor traces, 12-lead report or CTG
+                    ui.label(
+                        egui::RichText::new("VIEW:")
+                            .size(14.0)
+                            .color(egui::Color32::WHITE),
+                    );
+
+                    let mut layout = self.display.layout();
+                    egui::ComboBox::from_id_source("screen_layout")
+                        .selected_text(layout.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in ScreenLayout::OPTIONS {
+                                ui.selectable_value(&mut layout, option, option.to_string());
+                            }
+                        });
+                    self.display.set_layout(layout);
+
+                    // Trace gain; the calibration pulse beside each trace shows it
+                    ui.label(
+                        egui::RichText::new("GAIN:")
+                            .size(14.0)
+                            .color(egui::Color32::WHITE),
+                    );
+
+                    let mut gain = self.display.gain();
+                    egui::ComboBox::from_id_source("trace_gain")
+                        .selected_text(format!("{} mm/mV", gain))
+                        .show_ui(ui, |ui| {
+                            for option in EcgDisplay::GAIN_OPTIONS {
+                                ui.selectable_value(&mut gain, option, format!("{} mm/mV", option));
+                            }
+                        });
+                    self.display.set_gain(gain);
+
+                    ui.add_space(30.0);
+
+                    // Filter set of the display mode; the button edits it
+                    ui.label(
+                        egui::RichText::new("FILTER:")
+                            .size(14.0)
+                            .color(egui::Color32::WHITE),
+                    );
+
+                    let mut mode = self.filters.lock().unwrap().mode;
+                    egui::ComboBox::from_id_source("filter_mode")
+                        .selected_text(mode.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in FilterMode::OPTIONS {
+                                ui.selectable_value(&mut mode, option, option.to_string());
+                            }
+                        });
+                    self.filters.lock().unwrap().mode = mode;
+                    if ui.button("⚙").clicked() {
+                        self.show_filters_dialog = true;
+                    }
+
+                    ui.add_space(30.0);
+
+                    // Alarm controls
+                    let alarm_button = |ui: &mut egui::Ui, label: &str| {
+                        ui.add(
+                            egui::Button::new(
+                                egui::RichText::new(label)
+                                    .size(16.0)
+                                    .color(egui::Color32::WHITE),
+                            )
+                            .fill(egui::Color32::from_rgb(140, 0, 0))
+                            .min_size(egui::Vec2::new(70.0, 30.0)),
+                        )
+                    };
+                    if alarm_button(ui, "ACK").clicked() {
+                        self.alarms.lock().unwrap().acknowledge();
+                    }
+                    if alarm_button(ui, "🔕 SILENCE").clicked() {
+                        self.alarms.lock().unwrap().silence();
+                    }
+                    if alarm_button(ui, "ALARMS").clicked() {
+                        self.display.toggle_alarm_history();
+                    }
+                    if alarm_button(ui, "LIMITS").clicked() && self.limits_dialog.is_none() {
+                        self.limits_dialog = Some(self.alarms.lock().unwrap().limits());
+                    }
+
+                    ui.add_space(30.0);
+
                     // Status indicator
                     let status_color = if is_running {
                         egui::Color32::from_rgb(0, 255, 0)
@@ -308,12 +1068,21 @@ impl eframe::App for EcgMonitor {
                 });
             });
 
+        self.show_limits_dialog(ctx);
+        self.show_filters_dialog(ctx);
+        self.display
+            .set_filter_label(self.filters.lock().unwrap().summary());
+
         // Full screen patient monitor display
         egui::CentralPanel::default()
             .frame(egui::Frame::none().fill(egui::Color32::BLACK))
             .show(ctx, |ui| {
-                let samples = self.samples.lock().unwrap().clone();
-                self.display.draw_ecg(ui, &samples);
+                // Drawn under the lock; copying every multi-channel frame
+                // each repaint costs more than the data thread's short wait
+                let samples = self.samples.lock().unwrap();
+                let vitals = self.vitals.lock().unwrap().clone();
+                let alarms = self.alarms.lock().unwrap().status();
+                self.display.draw_ecg(ui, &samples, &vitals, &alarms);
             });
     }
 }
@@ -322,6 +1091,16 @@ impl eframe::App for EcgMonitor {
 async fn main() -> Result<(), eframe::Error> {
     tracing_subscriber::fmt::init();
 
+    // Command-line tools run instead of the monitor
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(result) = run_command(&args) {
+        if let Err(err) = result {
+            eprintln!("{}: {}", args[1], err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // let options = eframe::NativeOptions {
     //     viewport: egui::ViewportBuilder::default()
     //         .with_inner_size([WINDOW_WIDTH, WINDOW_HEIGHT])
@@ -357,6 +1136,218 @@ async fn main() -> Result<(), eframe::Error> {
     )
 }
 
+// `export-synthetic [file]`, `deidentify <input> <output> [options]` and
+// `evaluate <dir or edf> [options]` and `hrv <annotation file>`;
+// None when no command was given and the monitor should start
+fn run_command(args: &[String]) -> Option<Result<(), Box<dyn std::error::Error>>> {
+    match args.get(1)?.as_str() {
+        "export-synthetic" => Some(export_synthetic(
+            args.get(2).map_or("synthetic_ecg.edf", String::as_str),
+        )),
+        "deidentify" => Some(deidentify_command(&args[2..])),
+        "evaluate" => Some(evaluate_command(&args[2..])),
+        "hrv" => Some(hrv_command(&args[2..])),
+        "fetal" => Some(fetal_command(&args[2..])),
+        _ => None,
+    }
+}
+
+// Writes a minute of synthetic ECG as EDF+
+fn export_synthetic(filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let recording = RecordingDescriptor::synthetic();
+    let samples = EcgMonitor::generate_synthetic_ecg(recording.sample_rate);
+
+    let records = EcgMonitor::write_samples_edf(filename, &samples, &recording)?;
+    println!("Wrote {} ({} records)", filename, records);
+    Ok(())
+}
+
+// deidentify <input> <output> --key KEY [--pseudonym NAME]
+//            [--max-shift-days N] [--strip PATTERN]...
+fn deidentify_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: deidentify <input> <output> --key KEY [--pseudonym NAME] \
+                 [--max-shift-days N] [--strip PATTERN]...";
+    let (input, output) = match args {
+        [input, output, ..] => (input, output),
+        _ => return Err(usage.into()),
+    };
+
+    let mut key = None;
+    let mut pseudonym = None;
+    let mut max_shift_days = None;
+    let mut strip_patterns = Vec::new();
+    let mut flags = args[2..].iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--pseudonym" => pseudonym = Some(value.clone()),
+            "--key" => key = Some(value.as_str()),
+            "--max-shift-days" => max_shift_days = Some(value.parse()?),
+            "--strip" => strip_patterns.push(value.clone()),
+            _ => return Err(format!("unknown option {}\n{}", flag, usage).into()),
+        }
+    }
+
+    let key = key.ok_or_else(|| format!("--key is required\n{}", usage))?;
+    let mut options = deidentify::DeidentifyOptions::new(key)?;
+    options.pseudonym = pseudonym;
+    options.max_shift_days = max_shift_days.unwrap_or(options.max_shift_days);
+    options.strip_patterns = strip_patterns;
+
+    let report = deidentify::deidentify(input, output, &options)?;
+    println!(
+        "Wrote {} ({} records, dates shifted {} days, {} annotations removed)",
+        output, report.records, report.shift_days, report.annotations_removed
+    );
+    Ok(())
+}
+
+// evaluate <directory | file.edf> [--tolerance-ms N] [--channel N]
+fn evaluate_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: evaluate <directory | file.edf> [--tolerance-ms N] [--channel N]";
+    let target = std::path::Path::new(args.first().ok_or(usage)?);
+
+    let mut options = evaluation::EvaluationOptions::default();
+    let mut flags = args[1..].iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--tolerance-ms" => options.tolerance = value.parse::<f64>()? / 1000.0,
+            "--channel" => options.channel = value.parse()?,
+            _ => return Err(format!("unknown option {}\n{}", flag, usage).into()),
+        }
+    }
+
+    let scores = if target.is_dir() {
+        evaluation::evaluate_directory(target, &options)?
+    } else {
+        let annotation_path = std::path::PathBuf::from(format!("{}.qrs", target.display()));
+        vec![evaluation::evaluate_record(
+            target,
+            &annotation_path,
+            &options,
+        )?]
+    };
+
+    print!("{}", evaluation::summary_table(&scores));
+    Ok(())
+}
+
+// hrv <annotation file>: HRV of the annotated beats
+fn hrv_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let filename = args.first().ok_or("usage: hrv <annotation file>")?;
+    let beat_times: Vec<f64> = qrs_parser::QrsReader::load_annotations(filename, None)?
+        .iter()
+        .filter(|annotation| annotation.is_beat())
+        .map(|annotation| annotation.time)
+        .collect();
+    let metrics = hrv::analyze(&beat_times).ok_or("too few normal beats for HRV")?;
+
+    println!(
+        "Beats {}  NN intervals {}  ectopic removed {}",
+        metrics.beats, metrics.nn_intervals, metrics.ectopic_removed
+    );
+    println!("Mean NN   {:>9.1} ms", metrics.mean_nn);
+    println!("SDNN      {:>9.1} ms", metrics.sdnn);
+    println!("RMSSD     {:>9.1} ms", metrics.rmssd);
+    println!("pNN50     {:>9.1} %", metrics.pnn50);
+    println!("HRV TI    {:>9.1}", metrics.triangular_index);
+    println!("SD1/SD2   {:>9.1} / {:.1} ms", metrics.sd1, metrics.sd2);
+    println!("VLF       {:>9.1} ms²", metrics.spectrum.vlf);
+    println!("LF        {:>9.1} ms²", metrics.spectrum.lf);
+    println!("HF        {:>9.1} ms²", metrics.spectrum.hf);
+    if let Some(ratio) = metrics.spectrum.lf_hf_ratio() {
+        println!("LF/HF     {:>9.2}", ratio);
+    }
+    Ok(())
+}
+
+// fetal <file.edf> [--annotations FILE] [--save FILE]: maternal cancellation
+// and fetal QRS detection on the abdominal leads, checked against the scalp
+// lead and the annotations (default "<file.edf>.qrs" when it exists).
+// --save writes the cancelled leads with the fetal beats as EDF+.
+fn fetal_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: fetal <file.edf> [--annotations FILE] [--save FILE]";
+    let edf_path = std::path::Path::new(args.first().ok_or(usage)?);
+
+    let mut annotation_path = Some(std::path::PathBuf::from(format!(
+        "{}.qrs",
+        edf_path.display()
+    )))
+    .filter(|path| path.is_file());
+    let mut save_path = None;
+    let mut flags = args[1..].iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--annotations" => annotation_path = Some(std::path::PathBuf::from(value)),
+            "--save" => save_path = Some(value.clone()),
+            _ => return Err(format!("unknown option {}\n{}", flag, usage).into()),
+        }
+    }
+
+    let report = fetal_ecg::analyze_file(edf_path, annotation_path.as_deref())?;
+    let extraction = &report.extraction;
+    let maternal = extraction.maternal_times();
+    let fetal = extraction.fetal_times();
+
+    println!(
+        "Maternal  {:>5} beats  {:>6.1} BPM",
+        maternal.len(),
+        fetal_ecg::median_rate(&maternal).unwrap_or(0.0)
+    );
+    println!(
+        "Fetal     {:>5} beats  {:>6.1} BPM  (from {}, {:.0} s)",
+        fetal.len(),
+        fetal_ecg::median_rate(&fetal).unwrap_or(0.0),
+        extraction.residuals[extraction.fetal_channel].label,
+        report.duration
+    );
+
+    let comparisons = [
+        ("Direct lead", &report.vs_direct),
+        ("Annotations", &report.vs_annotations),
+    ];
+    for (name, comparison) in comparisons {
+        let Some(comparison) = comparison else {
+            continue;
+        };
+        let score = &comparison.score;
+        println!(
+            "vs {:<12} TP {:>4}  FN {:>4}  FP {:>4}  Se {:>6.2}%  +P {:>6.2}%  \
+             FHR error {:>5.1} BPM  within 10 BPM {:>5.1}%",
+            name,
+            score.true_positives,
+            score.false_negatives,
+            score.false_positives,
+            score.sensitivity() * 100.0,
+            score.positive_predictivity() * 100.0,
+            comparison.mean_error,
+            comparison.within_10_bpm * 100.0
+        );
+    }
+
+    if let Some(save_path) = save_path {
+        let annotations: Vec<EdfAnnotation> = fetal
+            .iter()
+            .map(|&onset| EdfAnnotation {
+                onset,
+                duration: None,
+                text: "N".to_string(),
+            })
+            .collect();
+        let records = edf_writer::write_edf(&save_path, &extraction.residuals, &annotations)?;
+        println!("Wrote {} ({} records)", save_path, records);
+    }
+    Ok(())
+}
+
 // Simple random number generation for synthetic data
 mod rand {
     use std::sync::atomic::{AtomicU64, Ordering};
@@ -0,0 +1,262 @@
+use crate::filters::Biquad;
+use std::collections::VecDeque;
+
+// Pan & Tompkins (1985) pass band: keeps most QRS energy while rejecting
+// baseline wander, P/T waves and mains interference
+const BANDPASS_LOW_HZ: f32 = 5.0;
+const BANDPASS_HIGH_HZ: f32 = 15.0;
+
+// Moving-window integration spans roughly the widest normal QRS
+const INTEGRATION_WINDOW_S: f32 = 0.150;
+
+// No second QRS can physiologically follow within 200 ms
+const REFRACTORY_S: f32 = 0.200;
+
+// Peaks this soon after a QRS may be T waves and must show a steep enough slope
+const T_WAVE_WINDOW_S: f32 = 0.360;
+
+// Signal and noise levels are learned from the first seconds before detecting
+const LEARNING_S: f32 = 2.0;
+
+// Recent samples kept to locate R peaks and to search back for missed beats
+const HISTORY_S: f32 = 2.5;
+
+// Search back once no beat has been found for this many average RR intervals
+const SEARCH_BACK_RR: f32 = 1.66;
+
+// Number of RR intervals in the running average
+const RR_HISTORY: usize = 8;
+
+// A local maximum of the integrated signal
+#[derive(Clone, Copy)]
+struct Peak {
+    index: u64,
+    amplitude: f32,
+    slope: f32,
+}
+
+// Streaming Pan–Tompkins QRS detector. Samples go in one at a time and the
+// sample index of each detected R peak comes out, usually a few hundred
+// milliseconds later (longer when a missed beat is recovered by search-back).
+pub struct QrsDetector {
+    fs: f32,
+    high_pass: Biquad,
+    low_pass: Biquad,
+    // Last four band-passed samples, newest first, for the five-point derivative
+    derivative_taps: [f32; 4],
+    integration: VecDeque<f32>,
+    integration_sum: f32,
+    integration_len: usize,
+    // Raw input and absolute slope history, oldest first
+    raw: VecDeque<f32>,
+    slopes: VecDeque<f32>,
+    history_len: usize,
+    // Integrated values at n-2 and n-1
+    previous: [f32; 2],
+    sample_count: u64,
+    learning_max: f32,
+    learning_sum: f32,
+    // Running signal and noise peak levels (SPKI and NPKI in the paper)
+    signal_level: f32,
+    noise_level: f32,
+    last_qrs: Option<Peak>,
+    rr_intervals: VecDeque<u64>,
+    // Largest rejected peak above the lower threshold since the last QRS
+    search_back: Option<Peak>,
+}
+
+impl QrsDetector {
+    pub fn new(fs: f32) -> Self {
+        let integration_len = ((INTEGRATION_WINDOW_S * fs).round() as usize).max(1);
+        Self {
+            fs,
+            high_pass: Biquad::high_pass(fs, BANDPASS_LOW_HZ),
+            low_pass: Biquad::low_pass(fs, BANDPASS_HIGH_HZ),
+            derivative_taps: [0.0; 4],
+            integration: VecDeque::with_capacity(integration_len),
+            integration_sum: 0.0,
+            integration_len,
+            raw: VecDeque::new(),
+            slopes: VecDeque::new(),
+            history_len: (HISTORY_S * fs) as usize,
+            previous: [0.0; 2],
+            sample_count: 0,
+            learning_max: 0.0,
+            learning_sum: 0.0,
+            signal_level: 0.0,
+            noise_level: 0.0,
+            last_qrs: None,
+            rr_intervals: VecDeque::with_capacity(RR_HISTORY),
+            search_back: None,
+        }
+    }
+
+    // Feeds the next sample and returns the index (counted from the first
+    // sample fed) of an R peak detected at this step, if any
+    pub fn process(&mut self, sample: f32) -> Option<u64> {
+        let index = self.sample_count;
+        self.sample_count += 1;
+
+        let filtered = self.low_pass.process(self.high_pass.process(sample));
+
+        // y[n] = (2x[n] + x[n-1] - x[n-3] - 2x[n-4]) / 8, scaled to per-second units
+        let [x1, _, x3, x4] = self.derivative_taps;
+        let slope = (2.0 * filtered + x1 - x3 - 2.0 * x4) * self.fs / 8.0;
+        self.derivative_taps = [filtered, x1, self.derivative_taps[1], x3];
+
+        let squared = slope * slope;
+        self.integration.push_back(squared);
+        self.integration_sum += squared;
+        if self.integration.len() > self.integration_len {
+            self.integration_sum -= self.integration.pop_front().unwrap_or(0.0);
+        }
+        let integrated = (self.integration_sum / self.integration_len as f32).max(0.0);
+
+        self.raw.push_back(sample);
+        self.slopes.push_back(slope.abs());
+        if self.raw.len() > self.history_len {
+            self.raw.pop_front();
+            self.slopes.pop_front();
+        }
+
+        let [before, peak_value] = self.previous;
+        self.previous = [peak_value, integrated];
+
+        let learning_samples = (LEARNING_S * self.fs) as u64;
+        if index < learning_samples {
+            self.learning_max = self.learning_max.max(integrated);
+            self.learning_sum += integrated;
+            if index + 1 == learning_samples {
+                self.signal_level = 0.25 * self.learning_max;
+                self.noise_level = 0.5 * self.learning_sum / learning_samples as f32;
+            }
+            return None;
+        }
+
+        // A peak of the integrated signal at n-1
+        if peak_value > before && peak_value >= integrated {
+            let peak = Peak {
+                index: index - 1,
+                amplitude: peak_value,
+                slope: self.max_slope(index - 1),
+            };
+            if let Some(r_peak) = self.classify(peak) {
+                return Some(r_peak);
+            }
+        }
+
+        self.search_back(index)
+    }
+
+    fn threshold(&self) -> f32 {
+        self.noise_level + 0.25 * (self.signal_level - self.noise_level)
+    }
+
+    fn classify(&mut self, peak: Peak) -> Option<u64> {
+        let refractory = (REFRACTORY_S * self.fs) as u64;
+        let t_wave_window = (T_WAVE_WINDOW_S * self.fs) as u64;
+
+        if let Some(last) = self.last_qrs {
+            let since_last = peak.index - last.index;
+            if since_last < refractory {
+                return None;
+            }
+            // A late, shallow peak is taken as the previous beat's T wave
+            if since_last < t_wave_window && peak.slope < 0.5 * last.slope {
+                self.noise_level = 0.125 * peak.amplitude + 0.875 * self.noise_level;
+                return None;
+            }
+        }
+
+        if peak.amplitude > self.threshold() {
+            self.signal_level = 0.125 * peak.amplitude + 0.875 * self.signal_level;
+            return Some(self.accept(peak));
+        }
+
+        self.noise_level = 0.125 * peak.amplitude + 0.875 * self.noise_level;
+        if peak.amplitude > 0.5 * self.threshold()
+            && self
+                .search_back
+                .is_none_or(|best| peak.amplitude > best.amplitude)
+        {
+            self.search_back = Some(peak);
+        }
+        None
+    }
+
+    // Accepts the best sub-threshold peak once a beat is overdue
+    fn search_back(&mut self, index: u64) -> Option<u64> {
+        let last = self.last_qrs?;
+        let average_rr = self.average_rr()?;
+        if (index - last.index) as f32 <= SEARCH_BACK_RR * average_rr {
+            return None;
+        }
+
+        let peak = self.search_back.take()?;
+        self.signal_level = 0.25 * peak.amplitude + 0.75 * self.signal_level;
+        Some(self.accept(peak))
+    }
+
+    fn accept(&mut self, peak: Peak) -> u64 {
+        if let Some(last) = self.last_qrs {
+            if self.rr_intervals.len() == RR_HISTORY {
+                self.rr_intervals.pop_front();
+            }
+            self.rr_intervals.push_back(peak.index - last.index);
+        }
+        self.last_qrs = Some(peak);
+        self.search_back = None;
+        self.locate_r_peak(peak.index)
+    }
+
+    fn average_rr(&self) -> Option<f32> {
+        if self.rr_intervals.is_empty() {
+            return None;
+        }
+        Some(self.rr_intervals.iter().sum::<u64>() as f32 / self.rr_intervals.len() as f32)
+    }
+
+    // Steepest band-passed slope inside the integration window ending at `index`
+    fn max_slope(&self, index: u64) -> f32 {
+        self.history_window(index)
+            .map(|(start, end)| {
+                self.slopes
+                    .range(start..end)
+                    .fold(0.0f32, |max, &slope| max.max(slope))
+            })
+            .unwrap_or(0.0)
+    }
+
+    // The integrated peak trails the QRS, so the R peak is the raw sample
+    // furthest from the window's mean level within the preceding window
+    fn locate_r_peak(&self, index: u64) -> u64 {
+        let Some((start, end)) = self.history_window(index) else {
+            return index;
+        };
+        let window = self.raw.range(start..end);
+        let mean = window.clone().sum::<f32>() / (end - start) as f32;
+
+        let offset = window
+            .enumerate()
+            .max_by(|(_, a), (_, b)| (*a - mean).abs().total_cmp(&(*b - mean).abs()))
+            .map_or(end - start - 1, |(offset, _)| offset);
+
+        self.first_history_index() + (start + offset) as u64
+    }
+
+    // History positions covering the integration window (plus filter delay)
+    // that ends at absolute sample `index`
+    fn history_window(&self, index: u64) -> Option<(usize, usize)> {
+        let first = self.first_history_index();
+        if index < first {
+            return None;
+        }
+        let end = ((index - first) as usize + 1).min(self.raw.len());
+        let span = self.integration_len + (0.05 * self.fs) as usize;
+        Some((end.saturating_sub(span), end)).filter(|(start, end)| start < end)
+    }
+
+    fn first_history_index(&self) -> u64 {
+        self.sample_count - self.raw.len() as u64
+    }
+}
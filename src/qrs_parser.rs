@@ -1,240 +1,709 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::edf_parser::{self, EdfAnnotation, EdfReader};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::Read;
+
+// MIT annotation codes that mark a pseudo-annotation rather than an event
+const SKIP: u8 = 59;
+const NUM: u8 = 60;
+const SUB: u8 = 61;
+const CHN: u8 = 62;
+const AUX: u8 = 63;
+
+// Null annotation, used by WFDB writers to move the time cursor
+const NOTQRS: u8 = 0;
+const NOTE: u8 = 22;
+
+// Tick rate of MIT files when neither the file nor the caller gives one
+const DEFAULT_TIME_RESOLUTION: f64 = 1000.0;
+const TIME_RESOLUTION_PREFIX: &str = "## time resolution:";
 
 #[derive(Debug, Clone)]
 pub struct QrsAnnotation {
     pub time: f64,
+    // Position in the record. MIT files count it in ticks of their time
+    // resolution, which are samples unless a "## time resolution" note says
+    // otherwise; None when the file gives only a time and the record's
+    // sampling rate is unknown.
+    pub sample: Option<i64>,
     pub annotation_type: char,
     pub subtype: u8,
     pub channel: u8,
+    pub num: u8,
+    pub aux: Option<String>,
+}
+
+// AAMI EC57 beat classes, the grouping of WFDB beat labels used for
+// arrhythmia analysis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatType {
+    Normal,
+    Supraventricular,
+    Ventricular,
+    Fusion,
+    Unclassified,
+}
+
+impl BeatType {
+    // Class of a WFDB beat mnemonic, or None for non-beat annotations
+    pub fn from_mnemonic(annotation_type: char) -> Option<Self> {
+        match annotation_type {
+            'N' | 'L' | 'R' | 'B' | 'e' | 'j' => Some(BeatType::Normal),
+            'A' | 'a' | 'J' | 'S' | 'n' => Some(BeatType::Supraventricular),
+            'V' | 'E' | 'r' => Some(BeatType::Ventricular),
+            'F' => Some(BeatType::Fusion),
+            '/' | 'f' | 'Q' => Some(BeatType::Unclassified),
+            _ => None,
+        }
+    }
+
+    // The AAMI class symbol, itself a WFDB beat mnemonic
+    pub fn mnemonic(&self) -> char {
+        match self {
+            BeatType::Normal => 'N',
+            BeatType::Supraventricular => 'S',
+            BeatType::Ventricular => 'V',
+            BeatType::Fusion => 'F',
+            BeatType::Unclassified => 'Q',
+        }
+    }
+
+    // Beats outside AAMI's Normal class. Atrial and nodal escape beats ('e',
+    // 'j') are in that class and don't count; ventricular and supraventricular
+    // escape beats ('E', 'n') do.
+    pub fn is_ectopic(&self) -> bool {
+        matches!(
+            self,
+            BeatType::Supraventricular | BeatType::Ventricular | BeatType::Fusion
+        )
+    }
+}
+
+impl QrsAnnotation {
+    pub fn is_beat(&self) -> bool {
+        QrsReader::is_qrs_annotation(self.annotation_type)
+    }
+
+    pub fn beat_type(&self) -> Option<BeatType> {
+        BeatType::from_mnemonic(self.annotation_type)
+    }
+
+    // Builds an annotation from a textual label; anything that is not a single
+    // WFDB mnemonic is kept as a comment with the label in `aux`
+    fn from_label(time: f64, sample: Option<i64>, label: &str) -> Self {
+        let mut chars = label.chars();
+        let (annotation_type, aux) = match (chars.next(), chars.next()) {
+            (Some(mnemonic), None) => (mnemonic, None),
+            // EDF+ exports usually mark beats with a plain "QRS" text
+            _ if label.eq_ignore_ascii_case("QRS") => ('Q', None),
+            _ => ('"', Some(label.to_string())),
+        };
+
+        QrsAnnotation {
+            time,
+            sample,
+            annotation_type,
+            subtype: 0,
+            channel: 0,
+            num: 0,
+            aux,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationFormat {
+    // WFDB binary annotation file (e.g. `.atr`, `.qrs`)
+    MitBinary,
+    // Text listing as printed by WFDB `rdann`
+    WfdbText,
+    // Comma-separated time/sample/type table
+    Csv,
+    // EDF+ file, or a raw stream of Time-stamped Annotation Lists
+    EdfTal,
+}
+
+impl fmt::Display for AnnotationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AnnotationFormat::MitBinary => "MIT binary",
+            AnnotationFormat::WfdbText => "WFDB text",
+            AnnotationFormat::Csv => "CSV",
+            AnnotationFormat::EdfTal => "EDF+ TAL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub enum QrsError {
+    Io(std::io::Error),
+    UnrecognizedFormat,
+    Malformed {
+        format: AnnotationFormat,
+        position: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for QrsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QrsError::Io(err) => write!(f, "failed to read annotation file: {}", err),
+            QrsError::UnrecognizedFormat => write!(f, "unrecognized annotation file format"),
+            QrsError::Malformed {
+                format,
+                position,
+                message,
+            } => write!(
+                f,
+                "malformed {} annotations at {}: {}",
+                format, position, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QrsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QrsError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for QrsError {
+    fn from(err: std::io::Error) -> Self {
+        QrsError::Io(err)
+    }
 }
 
 pub struct QrsReader;
 
 impl QrsReader {
-    pub fn load_annotations(filename: &str) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
-        let file = File::open(filename)?;
-        let mut reader = BufReader::new(file);
+    // `sample_rate` is the sampling rate of the annotated record, used to
+    // convert between sample numbers and times where the file gives only one
+    pub fn load_annotations(
+        filename: &str,
+        sample_rate: Option<f64>,
+    ) -> Result<Vec<QrsAnnotation>, QrsError> {
+        let mut file = File::open(filename)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let format = Self::detect_format(&contents).ok_or(QrsError::UnrecognizedFormat)?;
+
+        let mut annotations = match format {
+            AnnotationFormat::MitBinary => {
+                Self::decode_mit(&contents, sample_rate.unwrap_or(DEFAULT_TIME_RESOLUTION))?
+            }
+            AnnotationFormat::WfdbText => {
+                Self::parse_wfdb_text(&String::from_utf8_lossy(&contents), sample_rate)?
+            }
+            AnnotationFormat::Csv => {
+                Self::parse_csv(&String::from_utf8_lossy(&contents), sample_rate)?
+            }
+            AnnotationFormat::EdfTal if Self::is_edf_plus(&contents) => {
+                let edf_annotations = EdfReader::new(filename)
+                    .and_then(|mut reader| reader.read_annotations())
+                    .map_err(|err| QrsError::Malformed {
+                        format,
+                        position: 0,
+                        message: err.to_string(),
+                    })?;
+                Self::from_edf_annotations(&edf_annotations, sample_rate)
+            }
+            AnnotationFormat::EdfTal => Self::parse_tal(&contents, sample_rate)?,
+        };
+
+        // Sort annotations by time
+        annotations.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(annotations)
+    }
+
+    pub fn detect_format(bytes: &[u8]) -> Option<AnnotationFormat> {
+        if Self::is_edf_plus(bytes) || Self::is_tal_stream(bytes) {
+            return Some(AnnotationFormat::EdfTal);
+        }
+
+        if let Some(text) = Self::as_text(bytes) {
+            let mut lines = text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+            if let Some(first) = lines.next() {
+                if first.contains("Sample #") || Self::parse_wfdb_text_line(first).is_some() {
+                    return Some(AnnotationFormat::WfdbText);
+                }
+                if Self::looks_like_csv(first) {
+                    return Some(AnnotationFormat::Csv);
+                }
+            }
+            return None;
+        }
+
+        // Binary data only counts as MIT format if it decodes cleanly
+        match Self::decode_mit(bytes, DEFAULT_TIME_RESOLUTION) {
+            Ok(annotations) if !annotations.is_empty() => Some(AnnotationFormat::MitBinary),
+            _ => None,
+        }
+    }
+
+    fn looks_like_csv(first_line: &str) -> bool {
+        let columns: Vec<String> = first_line
+            .split(',')
+            .map(|column| column.trim().trim_matches('"').to_lowercase())
+            .collect();
+        if columns.len() < 2 {
+            return false;
+        }
+
+        // Either a numeric first row or a header naming a time/sample column
+        columns[0].parse::<f64>().is_ok()
+            || columns.iter().any(|column| {
+                ["time", "onset", "sample"]
+                    .iter()
+                    .any(|name| column.starts_with(name))
+            })
+    }
+
+    fn is_edf_plus(bytes: &[u8]) -> bool {
+        // Version field "0" padded to 8 bytes, "EDF+C"/"EDF+D" in the reserved field;
+        // BDF+ uses 0xFF "BIOSEMI" and "BDF+C"/"BDF+D" instead
+        bytes.len() >= 256
+            && ((&bytes[0..8] == b"0       " && &bytes[192..196] == b"EDF+")
+                || (&bytes[0..8] == b"\xffBIOSEMI" && &bytes[192..196] == b"BDF+"))
+    }
+
+    // An MIT file may also start with '+' and a digit, so the whole stream
+    // has to decode as TALs
+    fn is_tal_stream(bytes: &[u8]) -> bool {
+        let starts_with_onset =
+            bytes.len() >= 2 && (bytes[0] == b'+' || bytes[0] == b'-') && bytes[1].is_ascii_digit();
+        starts_with_onset
+            && bytes.contains(&0x14)
+            && edf_parser::parse_tals(bytes).is_ok_and(|tals| !tals.is_empty())
+    }
+
+    fn as_text(bytes: &[u8]) -> Option<&str> {
+        let text = std::str::from_utf8(bytes).ok()?;
+        let printable = text
+            .chars()
+            .all(|c| !c.is_control() || c == '\n' || c == '\r' || c == '\t');
+        printable.then_some(text)
+    }
+
+    // Decode a WFDB "MIT format" annotation stream.
+    //
+    // Every annotation is a little-endian 16-bit word: the top 6 bits hold the
+    // annotation code and the low 10 bits the sample interval since the
+    // previous annotation. Codes 59..=63 are pseudo-annotations: SKIP carries
+    // a 32-bit interval for the next annotation, while NUM, SUB, CHN and AUX
+    // modify the annotation that precedes them. A zero word ends the file.
+    fn decode_mit(bytes: &[u8], default_resolution: f64) -> Result<Vec<QrsAnnotation>, QrsError> {
+        let malformed = |position: usize, message: &str| QrsError::Malformed {
+            format: AnnotationFormat::MitBinary,
+            position,
+            message: message.to_string(),
+        };
 
         let mut annotations = Vec::new();
-        let mut time_resolution = 1000.0; // Default time resolution in Hz
-
-        // Try to read the header to get time resolution
-        if let Ok(header) = Self::read_header(&mut reader) {
-            time_resolution = header;
-        }
-
-        // Reset to beginning of file for annotation reading
-        reader.seek(SeekFrom::Start(0))?;
-
-        // Skip header if present (look for specific patterns)
-        let mut buffer = [0u8; 4];
-        if reader.read_exact(&mut buffer).is_ok() {
-            // Check if this looks like a header
-            if buffer.starts_with(b"## ") {
-                // Skip to end of line and continue reading
-                let mut line_buffer = Vec::new();
-                loop {
-                    let mut byte = [0u8; 1];
-                    if reader.read_exact(&mut byte).is_err() {
-                        break;
+        let mut time_resolution = default_resolution;
+
+        let mut pos = 0;
+        let mut sample: i64 = 0;
+        // NUM and CHN persist until changed; SUB and AUX apply to a single annotation
+        let mut channel = 0u8;
+        let mut num = 0u8;
+        let mut current: Option<(u8, QrsAnnotation)> = None;
+
+        while pos + 2 <= bytes.len() {
+            let word_pos = pos;
+            let word = u16::from_le_bytes([bytes[pos], bytes[pos + 1]]);
+            pos += 2;
+
+            let code = (word >> 10) as u8;
+            let data = word & 0x03ff;
+
+            match code {
+                NOTQRS if data == 0 => break,
+                SKIP => {
+                    if pos + 4 > bytes.len() {
+                        return Err(malformed(word_pos, "truncated SKIP annotation"));
                     }
-                    if byte[0] == b'\n' {
-                        break;
+                    // The skip interval is stored PDP-11 style: high word first
+                    let high = u16::from_le_bytes([bytes[pos], bytes[pos + 1]]) as u32;
+                    let low = u16::from_le_bytes([bytes[pos + 2], bytes[pos + 3]]) as u32;
+                    pos += 4;
+                    sample += ((high << 16) | low) as i32 as i64;
+                }
+                NUM => {
+                    num = data as u8;
+                    if let Some((_, annotation)) = current.as_mut() {
+                        annotation.num = num;
                     }
-                    line_buffer.push(byte[0]);
                 }
-            } else {
-                // Reset to beginning if no header found
-                reader.seek(SeekFrom::Start(0))?;
-            }
-        }
+                SUB => {
+                    if let Some((_, annotation)) = current.as_mut() {
+                        annotation.subtype = data as u8;
+                    }
+                }
+                CHN => {
+                    channel = data as u8;
+                    if let Some((_, annotation)) = current.as_mut() {
+                        annotation.channel = channel;
+                    }
+                }
+                AUX => {
+                    let len = data as usize;
+                    if pos + len > bytes.len() {
+                        return Err(malformed(word_pos, "truncated AUX annotation"));
+                    }
+                    let text = String::from_utf8_lossy(&bytes[pos..pos + len])
+                        .trim_end_matches('\0')
+                        .to_string();
+                    // AUX strings are padded to an even number of bytes
+                    pos += len + (len & 1);
+                    if let Some((_, annotation)) = current.as_mut() {
+                        annotation.aux = Some(text);
+                    }
+                }
+                _ => {
+                    if code != NOTQRS && !Self::is_defined_code(code) {
+                        return Err(malformed(
+                            word_pos,
+                            &format!("undefined annotation code {}", code),
+                        ));
+                    }
+
+                    if let Some(previous) = current.take() {
+                        Self::finish_annotation(previous, &mut annotations, &mut time_resolution);
+                    }
 
-        // Read QRS annotations
-        loop {
-            match Self::read_annotation(&mut reader, time_resolution) {
-                Ok(Some(time)) => annotations.push(time),
-                Ok(None) => continue, // Skip non-QRS annotations
-                Err(_) => break,      // End of file or error
+                    sample += data as i64;
+                    if sample < 0 {
+                        return Err(malformed(word_pos, "annotation before start of record"));
+                    }
+                    current = Some((
+                        code,
+                        QrsAnnotation {
+                            time: 0.0,
+                            sample: Some(sample),
+                            annotation_type: Self::annotation_mnemonic(code),
+                            subtype: 0,
+                            channel,
+                            num,
+                            aux: None,
+                        },
+                    ));
+                }
             }
         }
 
-        // If no annotations were read, try alternative parsing methods
-        if annotations.is_empty() {
-            annotations = Self::parse_alternative_format(filename, time_resolution)?;
+        if let Some(previous) = current.take() {
+            Self::finish_annotation(previous, &mut annotations, &mut time_resolution);
         }
 
-        // Sort annotations by time
-        annotations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // Sample numbers are only meaningful once the time resolution is known
+        for annotation in annotations.iter_mut() {
+            annotation.time = annotation.sample.unwrap_or_default() as f64 / time_resolution;
+        }
 
         Ok(annotations)
     }
 
-    fn read_header(reader: &mut BufReader<File>) -> Result<f64, Box<dyn std::error::Error>> {
-        let mut line = String::new();
-        let mut buffer = [0u8; 1];
+    fn finish_annotation(
+        (code, annotation): (u8, QrsAnnotation),
+        annotations: &mut Vec<QrsAnnotation>,
+        time_resolution: &mut f64,
+    ) {
+        if code == NOTQRS {
+            return;
+        }
 
-        // Read first line
-        while reader.read_exact(&mut buffer).is_ok() {
-            if buffer[0] == b'\n' {
-                break;
-            }
-            line.push(buffer[0] as char);
-        }
-
-        // Parse time resolution from header
-        if line.contains("time resolution:") {
-            let parts: Vec<&str> = line.split("time resolution:").collect();
-            if parts.len() > 1 {
-                let resolution_part = parts[1].trim();
-                let numbers: Vec<&str> = resolution_part.split_whitespace().collect();
-                if !numbers.is_empty() {
-                    if let Ok(resolution) = numbers[0].parse::<f64>() {
-                        return Ok(resolution);
-                    }
+        // A "## time resolution: N" note at the start of the file declares
+        // the tick rate used for every interval that follows
+        if code == NOTE && annotation.sample == Some(0) {
+            if let Some(resolution) = annotation
+                .aux
+                .as_deref()
+                .and_then(|aux| aux.strip_prefix(TIME_RESOLUTION_PREFIX))
+                .and_then(|rest| rest.split_whitespace().next())
+                .and_then(|value| value.parse::<f64>().ok())
+            {
+                if resolution > 0.0 {
+                    *time_resolution = resolution;
                 }
+                return;
             }
         }
 
-        Ok(1000.0) // Default resolution
+        annotations.push(annotation);
     }
 
-    fn read_annotation(
-        reader: &mut BufReader<File>,
-        time_resolution: f64,
-    ) -> Result<Option<f64>, Box<dyn std::error::Error>> {
-        // Try to read MIT-BIH annotation format
-        // Each annotation is typically 2 bytes for time + 2 bytes for annotation info
+    // rdann prints: "Time  Sample #  Type  Sub Chan  Num[\tAux]"
+    fn parse_wfdb_text(
+        text: &str,
+        sample_rate: Option<f64>,
+    ) -> Result<Vec<QrsAnnotation>, QrsError> {
+        let mut annotations = Vec::new();
 
-        // Read time (16-bit unsigned integer)
-        let time_raw = reader.read_u16::<LittleEndian>()?;
+        for (line_idx, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.contains("Sample #") {
+                continue;
+            }
 
-        // Read annotation type and additional info
-        let ann_type = reader.read_u8()?;
-        let _subtype = reader.read_u8()?;
+            let malformed = |message: String| QrsError::Malformed {
+                format: AnnotationFormat::WfdbText,
+                position: line_idx + 1,
+                message,
+            };
+            let (time, mut annotation) = Self::parse_wfdb_text_line(trimmed)
+                .ok_or_else(|| malformed(format!("unexpected line {:?}", trimmed)))?;
+            // Absolute clock times say nothing about the offset into the record
+            annotation.time = match (time, annotation.sample, sample_rate) {
+                (Some(time), _, _) => time,
+                (None, Some(sample), Some(fs)) => sample as f64 / fs,
+                _ => {
+                    return Err(malformed(
+                        "sample time needs the record's sampling rate".into(),
+                    ))
+                }
+            };
+            annotations.push(annotation);
+        }
 
-        // Convert time to seconds
-        let time_seconds = time_raw as f64 / time_resolution;
+        Ok(annotations)
+    }
 
-        // Check if this is a QRS annotation
-        // Common QRS annotation codes: N (Normal), L (Left bundle), R (Right bundle), etc.
-        if Self::is_qrs_annotation(ann_type) {
-            Ok(Some(time_seconds))
+    // The annotation and its elapsed time, which absolute-time listings leave out
+    fn parse_wfdb_text_line(line: &str) -> Option<(Option<f64>, QrsAnnotation)> {
+        // Absolute times are printed as "[hh:mm:ss.sss dd/mm/yyyy]"
+        let (time_field, rest) = if let Some(stripped) = line.strip_prefix('[') {
+            let close = stripped.find(']')?;
+            (None, &stripped[close + 1..])
         } else {
-            Ok(None)
-        }
-    }
-
-    fn is_qrs_annotation(ann_type: u8) -> bool {
-        // MIT-BIH annotation codes for QRS complexes
-        match ann_type {
-            1 => true,  // Normal beat
-            2 => true,  // Left bundle branch block beat
-            3 => true,  // Right bundle branch block beat
-            4 => true,  // Aberrant atrial premature beat
-            5 => true,  // Premature ventricular contraction
-            6 => true,  // Fusion of ventricular and normal beat
-            7 => true,  // Nodal (junctional) escape beat
-            8 => true,  // Atrial escape beat
-            9 => true,  // Nodal (junctional) premature beat
-            10 => true, // Ventricular escape beat
-            11 => true, // Left bundle branch block beat
-            12 => true, // Right bundle branch block beat
-            _ => {
-                // ASCII codes for beat annotations
-                match ann_type as char {
-                    'N' | 'L' | 'R' | 'A' | 'a' | 'J' | 'S' | 'V' | 'E' | 'j' | '/' | 'f' | 'Q' => {
-                        true
-                    }
-                    _ => false,
-                }
-            }
+            let mut split = line.splitn(2, char::is_whitespace);
+            (split.next(), split.next().unwrap_or_default())
+        };
+
+        let mut fields = rest.split_whitespace();
+        let sample: i64 = fields.next()?.parse().ok()?;
+        let label = fields.next()?;
+        let subtype: u8 = fields.next()?.parse().ok()?;
+        let channel: u8 = fields.next()?.parse().ok()?;
+        let num: u8 = fields.next()?.parse().ok()?;
+        let aux: Vec<&str> = fields.collect();
+
+        let time = match time_field {
+            Some(field) => Some(Self::parse_clock_time(field)?),
+            None => None,
+        };
+
+        let mut annotation = QrsAnnotation::from_label(0.0, Some(sample), label);
+        annotation.subtype = subtype;
+        annotation.channel = channel;
+        annotation.num = num;
+        if !aux.is_empty() {
+            annotation.aux = Some(aux.join(" "));
         }
+        Some((time, annotation))
     }
 
-    fn parse_alternative_format(
-        filename: &str,
-        time_resolution: f64,
-    ) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
-        let mut file = File::open(filename)?;
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents)?;
+    // "s.sss", "m:ss.sss" or "h:mm:ss.sss"
+    fn parse_clock_time(field: &str) -> Option<f64> {
+        field.split(':').try_fold(0.0, |total, part| {
+            part.parse::<f64>().ok().map(|value| total * 60.0 + value)
+        })
+    }
 
+    // Accepts an optional header naming `time`, `sample` and `type` columns;
+    // without one the first column is the time in seconds and the second the label
+    fn parse_csv(text: &str, sample_rate: Option<f64>) -> Result<Vec<QrsAnnotation>, QrsError> {
         let mut annotations = Vec::new();
-        let mut i = 0;
-
-        // Try different parsing strategies
-        while i < contents.len() {
-            // Strategy 1: Look for patterns that might be time stamps
-            if i + 4 <= contents.len() {
-                // Try reading as 32-bit integer (time in samples)
-                let sample_number = u32::from_le_bytes([
-                    contents[i],
-                    contents[i + 1],
-                    contents[i + 2],
-                    contents[i + 3],
-                ]);
-
-                // Convert to reasonable time (filter out obviously wrong values)
-                let time_seconds = sample_number as f64 / time_resolution;
-                if time_seconds > 0.0 && time_seconds < 3600.0 {
-                    // Reasonable time range (less than 1 hour)
-                    annotations.push(time_seconds);
-                }
-
-                i += 4;
-            } else {
-                break;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+            .peekable();
+
+        let mut time_col = Some(0);
+        let mut sample_col = None;
+        let mut type_col = Some(1);
+
+        if let Some((_, first)) = lines.peek() {
+            let columns: Vec<String> = first
+                .split(',')
+                .map(|column| column.trim().trim_matches('"').to_lowercase())
+                .collect();
+            if columns[0].parse::<f64>().is_err() {
+                let find = |names: &[&str]| {
+                    columns
+                        .iter()
+                        .position(|column| names.iter().any(|name| column.starts_with(name)))
+                };
+                time_col = find(&["time", "onset"]);
+                sample_col = find(&["sample"]);
+                type_col = find(&["type", "symbol", "label", "annotation"]);
+                lines.next();
             }
         }
 
-        // Remove duplicates and sort
-        annotations.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        annotations.dedup_by(|a, b| (*a - *b).abs() < 0.001); // Remove duplicates within 1ms
+        if time_col.is_none() && sample_col.is_none() {
+            return Err(QrsError::Malformed {
+                format: AnnotationFormat::Csv,
+                position: 1,
+                message: "no time or sample column".to_string(),
+            });
+        }
 
-        // If we got too many annotations, they're probably not QRS complexes
-        // Filter to keep only reasonable QRS intervals (0.3s to 2.0s apart)
-        if annotations.len() > 1000 {
-            let mut filtered = Vec::new();
-            filtered.push(annotations[0]);
+        for (line_idx, line) in lines {
+            let fields: Vec<&str> = line
+                .split(',')
+                .map(|field| field.trim().trim_matches('"'))
+                .collect();
+            let malformed = |message: String| QrsError::Malformed {
+                format: AnnotationFormat::Csv,
+                position: line_idx + 1,
+                message,
+            };
+
+            let parse_column = |col: Option<usize>| -> Result<Option<f64>, QrsError> {
+                match col {
+                    Some(col) => fields
+                        .get(col)
+                        .ok_or_else(|| malformed(format!("missing column {}", col + 1)))?
+                        .parse::<f64>()
+                        .map(Some)
+                        .map_err(|_| malformed(format!("non-numeric column {}", col + 1))),
+                    None => Ok(None),
+                }
+            };
 
-            for &time in annotations.iter().skip(1) {
-                if let Some(&last_time) = filtered.last() {
-                    let interval = time - last_time;
-                    if interval >= 0.3 && interval <= 2.0 {
-                        filtered.push(time);
-                    }
+            let time = parse_column(time_col)?;
+            let sample = parse_column(sample_col)?.map(|sample| sample.round() as i64);
+            let (time, sample) = match (time, sample, sample_rate) {
+                (Some(time), sample, fs) => {
+                    (time, sample.or(fs.map(|fs| (time * fs).round() as i64)))
                 }
-            }
+                (None, Some(sample), Some(fs)) => (sample as f64 / fs, Some(sample)),
+                (None, Some(_), None) => {
+                    return Err(malformed(
+                        "sample time needs the record's sampling rate".to_string(),
+                    ))
+                }
+                (None, None, _) => unreachable!(),
+            };
 
-            if !filtered.is_empty() {
-                annotations = filtered;
-            }
+            // Rows without a label are plain beat positions
+            let label = type_col
+                .and_then(|col| fields.get(col).copied())
+                .filter(|label| !label.is_empty())
+                .unwrap_or("N");
+
+            annotations.push(QrsAnnotation::from_label(time, sample, label));
         }
 
         Ok(annotations)
     }
 
-    pub fn generate_synthetic_qrs(duration_seconds: f64, heart_rate: f32) -> Vec<f64> {
-        let mut annotations = Vec::new();
-        let rr_interval = 60.0 / heart_rate as f64;
-        let mut current_time = 0.5; // Start after 0.5 seconds
-
-        // Add some variability to make it more realistic
-        let mut rng_seed = 12345u64;
+    fn parse_tal(bytes: &[u8], sample_rate: Option<f64>) -> Result<Vec<QrsAnnotation>, QrsError> {
+        let tals = edf_parser::parse_tals(bytes).map_err(|err| QrsError::Malformed {
+            format: AnnotationFormat::EdfTal,
+            position: 0,
+            message: err.to_string(),
+        })?;
+
+        let edf_annotations: Vec<EdfAnnotation> = tals
+            .iter()
+            .flat_map(|tal| {
+                tal.annotations
+                    .iter()
+                    .filter(|text| !text.is_empty())
+                    .map(move |text| EdfAnnotation {
+                        onset: tal.onset,
+                        duration: tal.duration,
+                        text: text.clone(),
+                    })
+            })
+            .collect();
+
+        Ok(Self::from_edf_annotations(&edf_annotations, sample_rate))
+    }
 
-        while current_time < duration_seconds {
-            annotations.push(current_time);
+    fn from_edf_annotations(
+        edf_annotations: &[EdfAnnotation],
+        sample_rate: Option<f64>,
+    ) -> Vec<QrsAnnotation> {
+        edf_annotations
+            .iter()
+            .map(|annotation| {
+                let sample = sample_rate.map(|fs| (annotation.onset * fs).round() as i64);
+                QrsAnnotation::from_label(annotation.onset, sample, &annotation.text)
+            })
+            .collect()
+    }
 
-            // Add heart rate variability (±10% of RR interval)
-            rng_seed = rng_seed.wrapping_mul(1664525).wrapping_add(1013904223);
-            let variability = (rng_seed as f64 / u64::MAX as f64 - 0.5) * 0.2; // ±10%
-            let next_interval = rr_interval * (1.0 + variability);
+    // Codes 42..=49 are left for user-defined annotation types
+    fn is_defined_code(code: u8) -> bool {
+        matches!(code, 1..=14 | 16 | 18..=49)
+    }
 
-            current_time += next_interval;
+    fn annotation_mnemonic(code: u8) -> char {
+        // Standard WFDB annotation code table (ecgcodes.h)
+        match code {
+            1 => 'N',  // Normal beat
+            2 => 'L',  // Left bundle branch block beat
+            3 => 'R',  // Right bundle branch block beat
+            4 => 'a',  // Aberrated atrial premature beat
+            5 => 'V',  // Premature ventricular contraction
+            6 => 'F',  // Fusion of ventricular and normal beat
+            7 => 'J',  // Nodal (junctional) premature beat
+            8 => 'A',  // Atrial premature beat
+            9 => 'S',  // Supraventricular premature beat
+            10 => 'E', // Ventricular escape beat
+            11 => 'j', // Nodal (junctional) escape beat
+            12 => '/', // Paced beat
+            13 => 'Q', // Unclassifiable beat
+            14 => '~', // Signal quality change
+            16 => '|', // Isolated QRS-like artifact
+            18 => 's', // ST change
+            19 => 'T', // T-wave change
+            20 => '*', // Systole
+            21 => 'D', // Diastole
+            22 => '"', // Comment annotation
+            23 => '=', // Measurement annotation
+            24 => 'p', // P-wave peak
+            25 => 'B', // Bundle branch block beat
+            26 => '^', // Non-conducted pacer spike
+            27 => 't', // T-wave peak
+            28 => '+', // Rhythm change
+            29 => 'u', // U-wave peak
+            30 => '?', // Learning
+            31 => '!', // Ventricular flutter wave
+            32 => '[', // Start of ventricular flutter/fibrillation
+            33 => ']', // End of ventricular flutter/fibrillation
+            34 => 'e', // Atrial escape beat
+            35 => 'n', // Supraventricular escape beat
+            36 => '@', // Link to external data
+            37 => 'x', // Non-conducted P-wave
+            38 => 'f', // Fusion of paced and normal beat
+            39 => '(', // Waveform onset
+            40 => ')', // Waveform end
+            41 => 'r', // R-on-T premature ventricular contraction
+            // User-defined codes have no standard mnemonic; the last digit of
+            // the code keeps them apart from each other and from Learning
+            42..=49 => char::from(b'0' + code - 40),
+            _ => '?',
         }
+    }
 
-        annotations
+    fn is_qrs_annotation(annotation_type: char) -> bool {
+        // Beat annotation mnemonics (WFDB isqrs)
+        BeatType::from_mnemonic(annotation_type).is_some()
     }
 }
@@ -0,0 +1,226 @@
+use crate::edf_parser::{align_signals, EdfReader, Timeline};
+use crate::leads::LeadDerivation;
+use crate::qrs_parser::{BeatType, QrsReader};
+use crate::{ChannelInfo, EcgFrame, RecordingDescriptor};
+use std::collections::VecDeque;
+
+// Seconds of EDF data pulled from disk per refill
+const CHUNK_SECONDS: f64 = 1.0;
+
+// Endless replay of a recording, one sample at a time
+pub enum SampleSource {
+    Edf(Box<EdfStream>),
+    Memory {
+        samples: Vec<EcgFrame>,
+        position: usize,
+    },
+}
+
+impl Iterator for SampleSource {
+    type Item = EcgFrame;
+
+    fn next(&mut self) -> Option<EcgFrame> {
+        match self {
+            SampleSource::Edf(stream) => stream.next(),
+            SampleSource::Memory { samples, position } => {
+                if samples.is_empty() {
+                    return None;
+                }
+                let sample = samples[*position].clone();
+                *position = (*position + 1) % samples.len();
+                Some(sample)
+            }
+        }
+    }
+}
+
+impl SampleSource {
+    // Why the source stopped producing samples, if it failed
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            SampleSource::Edf(stream) => stream.error.as_deref(),
+            SampleSource::Memory { .. } => None,
+        }
+    }
+}
+
+// Streams an EDF file from disk, keeping only about a second of samples in memory
+pub struct EdfStream {
+    reader: EdfReader,
+    timeline: Timeline,
+    fs: f64,
+    // Standard leads computed from the recorded ones
+    derivation: LeadDerivation,
+    // Annotated beats sorted by sample index, on the stream's sample grid
+    beat_samples: Vec<(usize, BeatType)>,
+    data_duration: f64,
+    // Seconds of recorded data already buffered
+    position: f64,
+    // Index of the next sample to be buffered
+    next_sample: usize,
+    buffer: VecDeque<EcgFrame>,
+    // Set when reading the file fails; the stream stops for good
+    error: Option<String>,
+}
+
+impl EdfStream {
+    pub fn open(
+        edf_path: &str,
+        annotation_path: &str,
+    ) -> Result<(RecordingDescriptor, Self), Box<dyn std::error::Error>> {
+        // Refuse corrupt headers rather than streaming with made-up scaling
+        let mut reader = EdfReader::open_validated(edf_path)?;
+
+        let mut recording = RecordingDescriptor {
+            source: edf_path.to_string(),
+            sample_rate: reader.get_sample_rate(),
+            channels: reader
+                .data_signals()
+                .into_iter()
+                .map(|signal| ChannelInfo {
+                    label: signal.label,
+                    unit: signal.unit,
+                })
+                .collect(),
+            patient: reader.get_header().patient_info(),
+            recording_info: reader.recording_info(),
+        };
+        if let Some(equipment) = &recording.recording_info.equipment {
+            println!("  recorded with {}", equipment);
+        }
+        if reader.start_offset() != 0.0 {
+            println!(
+                "  first sample at +{:.3} s from header start time",
+                reader.start_offset()
+            );
+        }
+
+        // The stream runs at the detection channel's own rate, so the QRS
+        // detector sees the samples as recorded; other channels are resampled
+        let signals = reader.data_signals();
+        let fs = signals
+            .get(recording.detection_channel())
+            .map(|signal| signal.fs)
+            .filter(|&fs| fs > 0.0)
+            .unwrap_or(recording.sample_rate as f64);
+        recording.sample_rate = fs as f32;
+        for signal in &signals {
+            let resampled = if signal.fs == fs { "" } else { " (resampled)" };
+            println!(
+                "  {} [{}] {} Hz{}",
+                signal.label, signal.unit, signal.fs, resampled
+            );
+        }
+        let derivation = recording.derive_leads();
+
+        // EDF+D records carry their own start times; fall back to back-to-back records
+        let timeline = reader.read_timeline().unwrap_or_else(|err| {
+            println!(
+                "  could not read record timeline ({}), assuming contiguous",
+                err
+            );
+            Timeline {
+                record_onsets: Vec::new(),
+                record_duration: 0.0,
+            }
+        });
+        for (start, end) in timeline.gaps() {
+            println!("  gap from {:.3} s to {:.3} s", start, end);
+        }
+
+        // Fall back to the EDF+ annotation channel when there is no annotation file
+        let mut beat_samples: Vec<(usize, BeatType)> =
+            QrsReader::load_annotations(annotation_path, Some(fs))
+                .or_else(|_| QrsReader::load_annotations(edf_path, Some(fs)))
+                .unwrap_or_else(|_| Vec::new())
+                .iter()
+                .filter_map(|annotation| {
+                    let beat_type = annotation.beat_type()?;
+                    let index = if timeline.record_onsets.is_empty() {
+                        (annotation.time * fs).round() as usize
+                    } else {
+                        timeline.sample_index(annotation.time, fs)?
+                    };
+                    Some((index, beat_type))
+                })
+                .collect();
+        beat_samples.sort_unstable_by_key(|&(index, _)| index);
+
+        let header = reader.get_header();
+        let data_duration = header.data_records as f64 * header.record_duration;
+
+        let stream = EdfStream {
+            reader,
+            timeline,
+            fs,
+            derivation,
+            beat_samples,
+            data_duration,
+            position: 0.0,
+            next_sample: 0,
+            buffer: VecDeque::new(),
+            error: None,
+        };
+
+        Ok((recording, stream))
+    }
+
+    fn refill(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.position >= self.data_duration {
+            return Ok(());
+        }
+
+        let signals = self.reader.read_range(self.position, CHUNK_SECONDS)?;
+        self.position += CHUNK_SECONDS;
+
+        // Channels recorded at other rates are resampled onto the stream's grid
+        for data_point in align_signals(&signals, self.fs) {
+            let index = self.next_sample;
+            self.next_sample += 1;
+
+            let mut frame = EcgFrame {
+                timestamp: self.timeline.sample_time(index, self.fs),
+                values: data_point,
+                beat: self
+                    .beat_samples
+                    .binary_search_by_key(&index, |&(sample, _)| sample)
+                    .ok()
+                    .map(|position| self.beat_samples[position].1),
+            };
+            self.derivation.extend(&mut frame);
+            self.buffer.push_back(frame);
+        }
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Option<EcgFrame> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        if self.buffer.is_empty() {
+            self.try_refill()?;
+        }
+
+        // Loop back to the start once the recording is exhausted
+        if self.buffer.is_empty() {
+            self.position = 0.0;
+            self.next_sample = 0;
+            self.try_refill()?;
+        }
+
+        self.buffer.pop_front()
+    }
+
+    // Refills the buffer, stopping the stream if the file can't be read
+    fn try_refill(&mut self) -> Option<()> {
+        if let Err(err) = self.refill() {
+            let message = format!("EDF read error at {:.1} s: {}", self.position, err);
+            println!("{}", message);
+            self.error = Some(message);
+            return None;
+        }
+        Some(())
+    }
+}